@@ -10,53 +10,135 @@
 //! - leading and trailing whitespace is ignored.
 //! - whitespace surrounding group names, variables, and values are removed.
 //! - whitespace within group names, variable names, and values is allowed.
+//! - a line indented more deeply than the assignment before it continues that
+//!   assignment's value; the (trimmed) lines of the value are joined by
+//!   newlines. If the assignment line has no value, the value begins on the
+//!   first continuation line. Indented comment lines within the value are
+//!   skipped. Note that an indented assignment or group header is also a
+//!   continuation line; for files that indent lines only for readability,
+//!   disable continuation lines via [ParserOptions::continuation_lines].
+//! - a value beginning with a double quote ('"') extends to the closing quote,
+//!   preserving any whitespace, and may contain the escape sequences `\n`,
+//!   `\t`, `\"`, `\\`, and `\u{XXXX}`. Values that would not otherwise be
+//!   read back unchanged are quoted when written.
 //! - a semicolon (';') at the beginning of a line denotes a comment.
-//! - if a variable is set multiple times in a file, the last one read is kept.
+//! - if a variable is set multiple times in a file, the last one read is its
+//!   value; every value read is available as a list (see the
+//!   [list module](list)).
+//!
+//! The comment prefixes, separators, case sensitivity, name of the DEFAULT
+//! group, and handling of duplicate variables can be changed via
+//! [ParserOptions] to read other INI dialects; a `Config` read with custom
+//! options is written back in the same dialect.
+//!
+//! A group can inherit the variables it does not set from another group, and
+//! lookups can fall back to the DEFAULT group; see [Config::set_parent],
+//! [ParserOptions::inheritance], and [ParserOptions::default_fallback].
+//! Environment-specific variations of groups are selected via
+//! [Config::with_profile].
 //!
 //! Multiple INI files can be merged into a single [Config]; variables read in a
 //! later file replace any set in prior configuration files.
 //!
+//! A `Config` remembers the order in which groups and variables were read, so
+//! writing it produces a stable file; see [WriteOptions] to sort it instead.
+//! [Config::diff] lists the differences between two configurations.
+//!
 //! The configuration values can be modified via the [Config::set] method; `set`
 //! also provides a convenient API for setting default values prior to reading a
-//! configuration file.
+//! configuration file. A loaded configuration can be edited in place via
+//! [Config::insert], [Config::remove], [Config::entry], and similar methods.
+//!
+//! Configuration files are read via [Config::read_from_file_blocking]; the
+//! `config_async_std` and `config_tokio` features provide asynchronous wrappers
+//! for their respective runtimes. A `Config` can also be parsed from a string
+//! or any (async) reader via [Config::from_reader] and friends.
+//! Files are written via [Config::write_to_file_blocking] and its asynchronous
+//! counterparts, which replace a file atomically, keeping its permissions.
+//!
+//! Values can be overridden by environment variables via [Config::merge_env]
+//! and by command-line arguments via [Config::merge_args].
+//!
+//! Values may reference other values and environment variables as `${var}`,
+//! `${group:var}`, or `${env:NAME}`; references are expanded by
+//! [Config::interpolate] or [Config::get_interpolated].
+//!
+//! Configuration files can include other files when
+//! [ParserOptions::includes] is enabled, and a directory of files can be read
+//! via [Config::read_from_dir_blocking].
+//!
+//! A [ConfigLoader] discovers and merges the system, user, and project
+//! configuration files for an application, along with any overrides. With the
+//! `config_watch` feature, a [watch::ConfigWatcher] reloads the configuration
+//! whenever those files change.
+//!
+//! To edit a configuration file without losing its comments and layout, use a
+//! [Document] instead.
+//!
+//! Values can be parsed into any type implementing [FromStr] via
+//! [Config::get_as]; the [Origin] of each value is tracked so that parse errors
+//! can point to the file and line that set it. With the `config_schema`
+//! feature, an entire `Config` can be checked against a [schema::Schema].
+//!
+//! With the `config_serde` feature, a [Config] can be deserialized into any
+//! type implementing `serde::Deserialize` via [from_config], and any type
+//! implementing `serde::Serialize` can be converted to a `Config` via
+//! [to_config].
 
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
     ops::Index,
+    str::FromStr,
     fmt,
     io,
 };
 
-#[cfg(feature = "config_global")]
-use once_cell::sync::OnceCell;
-
 use super::iter::uniq::Uniq;
 
+pub mod diff;
+pub mod document;
+pub mod entry;
+pub mod env;
+pub mod include;
+pub mod interpolate;
+pub mod list;
+pub mod loader;
+pub mod profile;
+mod args;
+mod parser;
+mod quote;
+mod write;
+
+pub use diff::ConfigDiff;
+pub use document::Document;
+pub use entry::Entry;
+pub use env::EnvOptions;
+pub use interpolate::InterpolationError;
+pub use list::ListStyle;
+pub use loader::ConfigLoader;
+pub use parser::{Duplicates, ParserOptions};
+use parser::Parser;
+
+#[cfg(feature = "config_serde")]
+pub mod de;
+#[cfg(feature = "config_serde")]
+pub mod ser;
 
 #[cfg(feature = "config_global")]
-static CONFIG: OnceCell<Config> = OnceCell::new();
+mod global;
+#[cfg(feature = "config_schema")]
+pub mod schema;
+#[cfg(feature = "config_watch")]
+pub mod watch;
 
 #[cfg(feature = "config_global")]
-/// Make the provided [Config] available globally.
-///
-/// This function can only be called once; if called again, it will return an
-/// error containing the provided `Config` object.
-///
-/// Use [Config::global] to obtain the global `Config` instance.
-///
-/// # Example
-///
-/// ```
-/// # use utility_belt::config::{Config, set_global};
-/// let conf = Config::default();
-/// set_global(conf).expect("Global configuration was already initialized");
-/// let conf = Config::global();
-/// ```
-pub fn set_global(conf: Config) -> Result<(), Config> {
-    CONFIG.set(conf)?;
-    Ok(())
-}
+pub use global::{override_global, set_global, GlobalOverride};
+#[cfg(feature = "config_serde")]
+pub use de::from_config;
+#[cfg(feature = "config_serde")]
+pub use ser::{to_config, to_string};
+
 
 /// The key used to look up a configuration value.
 ///
@@ -64,142 +146,540 @@ pub fn set_global(conf: Config) -> Result<(), Config> {
 // This is not efficient for a large number of keys.
 type Key = (String, String);
 
+/// Where a configuration value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The value was set programmatically, e.g. via [Config::set].
+    Set,
+    /// The value was read from the given line of a file.
+    File { path: PathBuf, line: u32 },
+    /// The value was read from the named environment variable.
+    Env(String),
+    /// The value was read from the command-line argument at the given
+    /// position.
+    Arg { index: usize },
+    /// The value was read from the named profile group and overlaid on its
+    /// base group; see [Config::with_profile].
+    Profile { group: String, origin: Box<Origin> },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Origin::Set => write!(f, "set programmatically"),
+            Origin::File { ref path, ref line } =>
+                write!(f, "line {} in {}", line, path.to_string_lossy()),
+            Origin::Env(ref name) =>
+                write!(f, "environment variable {}", name),
+            Origin::Arg { ref index } =>
+                write!(f, "command-line argument {}", index),
+            Origin::Profile { ref group, ref origin } =>
+                write!(f, "{} (profile group {})", origin, group),
+        }
+    }
+}
+
+/// A configuration value along with its [Origin].
+#[derive(Debug, Clone)]
+struct Value {
+    val: String,
+    origin: Origin,
+    /// Every value assigned, in order, if the variable was assigned more than
+    /// once in a file or set via [Config::set_list]; otherwise empty.
+    list: Vec<String>,
+}
+
+impl Value {
+    fn new(val: String, origin: Origin) -> Self {
+        Self { val, origin, list: vec![] }
+    }
+
+    /// Add the value of a repeated assignment to the list. If `replace` is
+    /// true, it also becomes the variable's value.
+    fn repeat(&mut self, val: String, origin: Origin, replace: bool) {
+        if self.list.is_empty() { self.list.push(self.val.clone()); }
+        self.list.push(val.clone());
+
+        if replace {
+            self.val = val;
+            self.origin = origin;
+        }
+    }
+}
+
+/// An insertion-ordered map of configuration values.
+#[derive(Debug, Clone, Default)]
+struct Values {
+    entries: Vec<(Key, Value)>,
+    index: HashMap<Key, usize>,
+}
+
+impl Values {
+    fn get_key_value(&self, key: &Key) -> Option<&(Key, Value)> {
+        self.index.get(key).map(|i| &self.entries[*i])
+    }
+
+    fn get_mut(&mut self, key: &Key) -> Option<&mut Value> {
+        let i = *self.index.get(key)?;
+        Some(&mut self.entries[i].1)
+    }
+
+    /// Insert a value, returning the value it replaced. If the key is already
+    /// present, the key retains its original position.
+    fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        match self.index.get(&key) {
+            Some(i) => Some(std::mem::replace(&mut self.entries[*i].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            },
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(Key, Value)> {
+        self.entries.iter()
+    }
+
+    /// Remove a value, returning it if it was present.
+    fn remove(&mut self, key: &Key) -> Option<Value> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+
+        for (k, _) in &self.entries[i..] {
+            *self.index.get_mut(k).expect("Keys must be indexed") -= 1;
+        }
+        Some(value)
+    }
+
+    /// Keep only the entries for which `f` returns `true`.
+    fn retain(&mut self, mut f: impl FnMut(&Key, &Value) -> bool) {
+        self.entries.retain(|(k, v)| f(k, v));
+        self.reindex();
+    }
+
+    /// Rebuild the index after modifying the entries' keys or positions.
+    fn reindex(&mut self) {
+        self.index = self.entries.iter()
+            .enumerate()
+            .map(|(i, (k, _))| (k.clone(), i))
+            .collect();
+    }
+}
+
+impl IntoIterator for Values {
+    type Item = (Key, Value);
+    type IntoIter = std::vec::IntoIter<(Key, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
 /// The Configuration object.
-#[derive(Debug, Default)]
+///
+/// Groups and variables are kept in the order they were first set or read,
+/// except that the DEFAULT group is always first.
+///
+/// A group may inherit the variables it does not set from a parent group (see
+/// [Config::set_parent]), and with [ParserOptions::default_fallback], from the
+/// DEFAULT group; [Config::resolve] reports which group supplied a value.
+#[derive(Debug, Clone, Default)]
 pub struct Config {
-    values: HashMap<Key, String>,
+    values: Values,
+    /// The parent of each group that inherits from another.
+    parents: HashMap<String, String>,
+    options: ParserOptions,
 }
 
 impl Config {
-    #[cfg(feature = "config_global")]
-    /// Retrieve the global [Config] instance.
+    /// Read a [Config] from the INI file at the path specified.
+    ///
+    /// With [ParserOptions::includes] enabled, any files listed in the file's
+    /// `[include]` group are read as well; see the
+    /// [include module](mod@include) documentation.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configuration file if successfully read; otherwise returns a
+    /// list of errors that occurred while reading or parsing the file.
+    pub fn read_from_file_blocking(path: &Path) -> Result<Self, Vec<FileError>>
+    {
+        Self::read_from_file_blocking_with(path, &ParserOptions::default())
+    }
+
+    /// Read a [Config] from the INI file at the path specified, using the
+    /// dialect described by `options`.
+    ///
+    /// See [Config::read_from_file_blocking] for more information.
+    pub fn read_from_file_blocking_with(path: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        include::read_file(path, options, &mut vec![])
+    }
+
+    /// Read a [Config] from a single INI file, without resolving includes.
+    fn read_single_file(path: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        use std::{fs::File, io::BufReader};
+
+        let f = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => return Err(vec![FileError::IO((path.into(), e.kind()))]),
+        };
+
+        Self::from_reader_with(BufReader::new(f), path, options)
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously read a [Config] from the INI file at the path specified,
+    /// using the async-std runtime.
+    ///
+    /// See [Config::read_from_file_blocking] for more information.
+    pub async fn read_from_file(path: &Path) -> Result<Self, Vec<FileError>> {
+        Self::read_from_file_with(path, &ParserOptions::default()).await
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously read a [Config] from the INI file at the path specified,
+    /// using the dialect described by `options` and the async-std runtime.
     ///
-    /// # Panics
+    /// See [Config::read_from_file_blocking] for more information.
+    pub async fn read_from_file_with(path: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        let path = path.to_owned();
+        let options = options.clone();
+
+        async_std::task::spawn_blocking(
+            move || Self::read_from_file_blocking_with(&path, &options)
+        ).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously read a [Config] from the INI file at the path specified,
+    /// using the tokio runtime.
+    ///
+    /// See [Config::read_from_file_blocking] for more information.
+    pub async fn read_from_file_tokio(path: &Path)
+    -> Result<Self, Vec<FileError>> {
+        Self::read_from_file_tokio_with(path, &ParserOptions::default()).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously read a [Config] from the INI file at the path specified,
+    /// using the dialect described by `options` and the tokio runtime.
+    ///
+    /// See [Config::read_from_file_blocking] for more information.
+    pub async fn read_from_file_tokio_with(path: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        let owned = path.to_owned();
+        let options = options.clone();
+
+        spawn_blocking_tokio(
+            path,
+            move || Self::read_from_file_blocking_with(&owned, &options)
+        ).await.unwrap_or_else(|e| Err(vec![e]))
+    }
+
+    /// Parse a [Config] from the lines of `reader`.
     ///
-    /// Panics if no global configuration has been initialized. Use [set_global]
-    /// to register a global `Config`.
+    /// `source_name` names the source of the data (typically a file path); it
+    /// is used to describe the [Origin] of values and the location of errors.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configuration if successfully read; otherwise returns a
+    /// list of errors that occurred while reading or parsing the data.
     ///
     /// # Example
     ///
     /// ```
-    /// # use utility_belt::config::{Config, set_global};
-    /// let conf = Config::default();
-    /// set_global(conf).expect("Global configuration was already initialized");
-    /// let conf = Config::global();
+    /// # use utility_belt::config::Config;
+    /// let data = "[Server]\nport = 8080\n";
+    /// let conf = Config::from_reader(data.as_bytes(), "embedded").unwrap();
+    /// assert_eq!(conf[("Server", "port")], "8080");
     /// ```
-    pub fn global() -> &'static Config {
-        CONFIG.get().expect("Global Config is not initialized")
+    pub fn from_reader(reader: impl io::BufRead, source_name: impl AsRef<Path>)
+    -> Result<Self, Vec<FileError>> {
+        Self::from_reader_with(reader, source_name, &ParserOptions::default())
     }
 
-    /// Read a [Config] from the INI file at the path specified.
+    /// Parse a [Config] from the lines of `reader`, using the dialect described
+    /// by `options`.
     ///
-    /// # Returns
+    /// See [Config::from_reader] for more information.
+    pub fn from_reader_with(
+        reader: impl io::BufRead,
+        source_name: impl AsRef<Path>,
+        options: &ParserOptions,
+    ) -> Result<Self, Vec<FileError>> {
+        let mut parser = Parser::new(source_name.as_ref(), options);
+
+        for line in reader.lines() {
+            match line {
+                Ok(line) => parser.parse_line(&line),
+                Err(e) => {
+                    parser.read_error(e);
+                    break;
+                },
+            }
+        }
+
+        parser.finish()
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously parse a [Config] from the lines of an async-std
+    /// `reader`.
     ///
-    /// Returns the configuration file if successfully read; otherwise returns a
-    /// list of errors that occurred while reading or parsing the file.
-    pub async fn read_from_file(path: &Path) -> Result<Self, Vec<FileError>> {
-        use async_std::{
-            fs::File,
-            io::{prelude::*, BufReader},
-        };
+    /// See [Config::from_reader] for more information.
+    pub async fn from_async_reader<R>(reader: R, source_name: impl AsRef<Path>)
+    -> Result<Self, Vec<FileError>>
+        where R: async_std::io::BufRead + Unpin,
+    {
+        let options = ParserOptions::default();
+        Self::from_async_reader_with(reader, source_name, &options).await
+    }
 
-        let f = match File::open(path).await {
-            Ok(f) => f,
-            Err(e) => return Err(vec![e.into()]),
-        };
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously parse a [Config] from the lines of an async-std
+    /// `reader`, using the dialect described by `options`.
+    ///
+    /// See [Config::from_reader] for more information.
+    pub async fn from_async_reader_with<R>(
+        reader: R,
+        source_name: impl AsRef<Path>,
+        options: &ParserOptions,
+    ) -> Result<Self, Vec<FileError>>
+        where R: async_std::io::BufRead + Unpin,
+    {
+        use async_std::{io::prelude::BufReadExt as _, stream::StreamExt as _};
 
-        let mut reader = BufReader::new(f);
-        let mut line = String::new();
-        let mut cnt = 0;
+        let mut parser = Parser::new(source_name.as_ref(), options);
+        let mut lines = reader.lines();
 
-        let mut errors = vec![];
-        let mut map = HashMap::new();
-        let mut group = String::from("DEFAULT");
+        while let Some(line) = lines.next().await {
+            match line {
+                Ok(line) => parser.parse_line(&line),
+                Err(e) => {
+                    parser.read_error(e);
+                    break;
+                },
+            }
+        }
+
+        parser.finish()
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously parse a [Config] from the lines of a tokio `reader`.
+    ///
+    /// See [Config::from_reader] for more information.
+    pub async fn from_tokio_reader<R>(reader: R, source_name: impl AsRef<Path>)
+    -> Result<Self, Vec<FileError>>
+        where R: tokio::io::AsyncBufRead + Unpin,
+    {
+        let options = ParserOptions::default();
+        Self::from_tokio_reader_with(reader, source_name, &options).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously parse a [Config] from the lines of a tokio `reader`,
+    /// using the dialect described by `options`.
+    ///
+    /// See [Config::from_reader] for more information.
+    pub async fn from_tokio_reader_with<R>(
+        reader: R,
+        source_name: impl AsRef<Path>,
+        options: &ParserOptions,
+    ) -> Result<Self, Vec<FileError>>
+        where R: tokio::io::AsyncBufRead + Unpin,
+    {
+        use tokio::io::AsyncBufReadExt as _;
+
+        let mut parser = Parser::new(source_name.as_ref(), options);
+        let mut lines = reader.lines();
 
         loop {
-            match reader.read_line(&mut line).await {
-                Ok(len) => if len == 0 { break; },
+            match lines.next_line().await {
+                Ok(Some(line)) => parser.parse_line(&line),
+                Ok(None) => break,
                 Err(e) => {
-                    errors.push(e.into());
-                    continue;
-                }
-            };
-
-            cnt += 1;
-            line = line.trim().into();
-            if line.is_empty() { continue; }
-
-            if line.starts_with(';') {
-                line.clear();
-                continue;
-            } else if line.starts_with('[') {
-                if line.ends_with(']') {
-                    group = line[1..line.len()-1].trim().into();
-                } else {
-                    errors.push(FileError::Parse {
-                        file: path.to_owned(),
-                        msg: "Missing closing bracket for group name".into(),
-                        data: line.to_owned(),
-                        line: cnt,
-                    });
-                }
-            } else if let Some((var, val)) = line.split_once('=') {
-                // We'll allow empty values, but not variables.
-                let var = var.trim_end().to_string();
-
-                if var.is_empty() {
-                    errors.push(FileError::Parse {
-                        file: path.to_owned(),
-                        msg: "Assignment requires a variable name".into(),
-                        data: line.to_owned(),
-                        line: cnt,
-                    });
-                } else {
-                    map.insert(
-                        (group.clone(), var),
-                        val.trim_start().to_string()
-                    );
-                }
-            } else {
-                errors.push(FileError::Parse {
-                    file: path.to_owned(),
-                    msg: "Expected a variable assignment".into(),
-                    data: line.to_owned(),
-                    line: cnt,
-                });
+                    parser.read_error(e);
+                    break;
+                },
             }
-            line.clear();
         }
 
-        if errors.is_empty() {
-            Ok(Self { values: map })
-        } else {
-            Err(errors)
+        parser.finish()
+    }
+
+    /// The [ParserOptions] describing the dialect this configuration was read
+    /// in, and is written in.
+    pub fn parser_options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Use the dialect described by `options` when writing this configuration
+    /// and looking up its values.
+    ///
+    /// If `options` are not case-sensitive, existing group and variable names
+    /// are converted to lower case.
+    pub fn with_parser_options(mut self, options: ParserOptions) -> Self {
+        let values = std::mem::take(&mut self.values);
+        self.options = options;
+
+        for ((group, var), value) in values {
+            let key = self.key(&group, &var);
+            self.values.insert(key, value);
         }
+
+        let parents = std::mem::take(&mut self.parents);
+        for (group, parent) in parents {
+            self = self.set_parent(&group, &parent);
+        }
+        self
     }
 
     /// Write this configuration to the given file. If the file exists, it is
     /// replaced with the contents of this configuration.
-    pub fn write_to_file(&self, path: &Path) -> Result<(), FileError> {
-        // TODO: Make this function async. As of async_std 1.9.0, writes are
-        // incomplete and the function hangs indefinitely. Debug and file issue.
-        use std::{
-            fs::File,
-            io::Write as _,
+    ///
+    /// The file is replaced atomically, keeping its permissions; see
+    /// [Config::write_to_file_blocking_with].
+    pub fn write_to_file_blocking(&self, path: &Path)
+    -> Result<(), FileError> {
+        self.write_to_file_blocking_with(path, &WriteOptions::default())
+    }
+
+    /// Write this configuration to the given file with the specified
+    /// [WriteOptions]. If the file exists, it is replaced with the contents of
+    /// this configuration.
+    ///
+    /// The configuration is written to a temporary file in the same directory,
+    /// which is synced to disk and then renamed over the original, so the file
+    /// is never left partially written. The original file's permissions are
+    /// kept, as are its owner and group where the process is permitted to set
+    /// them. If `path` is a symbolic link, the file it refers to is replaced.
+    pub fn write_to_file_blocking_with(
+        &self,
+        path: &Path,
+        options: &WriteOptions,
+    ) -> Result<(), FileError> {
+        write::write_file(path, &self.to_string_with(options), options.backup)
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously write this configuration to the given file, using the
+    /// async-std runtime.
+    ///
+    /// See [Config::write_to_file_blocking_with] for more information.
+    pub async fn write_to_file(&self, path: &Path) -> Result<(), FileError> {
+        self.write_to_file_with(path, &WriteOptions::default()).await
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously write this configuration to the given file with the
+    /// specified [WriteOptions], using the async-std runtime.
+    ///
+    /// See [Config::write_to_file_blocking_with] for more information.
+    pub async fn write_to_file_with(&self, path: &Path, options: &WriteOptions)
+    -> Result<(), FileError> {
+        let path = path.to_owned();
+        let text = self.to_string_with(options);
+        let backup = options.backup;
+
+        async_std::task::spawn_blocking(
+            move || write::write_file(&path, &text, backup)
+        ).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously write this configuration to the given file, using the
+    /// tokio runtime.
+    ///
+    /// See [Config::write_to_file_blocking_with] for more information.
+    pub async fn write_to_file_tokio(&self, path: &Path)
+    -> Result<(), FileError> {
+        self.write_to_file_tokio_with(path, &WriteOptions::default()).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously write this configuration to the given file with the
+    /// specified [WriteOptions], using the tokio runtime.
+    ///
+    /// See [Config::write_to_file_blocking_with] for more information.
+    pub async fn write_to_file_tokio_with(
+        &self,
+        path: &Path,
+        options: &WriteOptions,
+    ) -> Result<(), FileError> {
+        let owned = path.to_owned();
+        let text = self.to_string_with(options);
+        let backup = options.backup;
+
+        spawn_blocking_tokio(
+            path,
+            move || write::write_file(&owned, &text, backup)
+        ).await?
+    }
+
+    /// Format this configuration as INI text with the specified
+    /// [WriteOptions].
+    ///
+    /// The [Display](fmt::Display) implementation formats the configuration
+    /// with the default options.
+    pub fn to_string_with(&self, options: &WriteOptions) -> String {
+        let mut text = String::new();
+        self.format(&mut text, options)
+            .expect("Formatting to a String cannot fail");
+        text
+    }
+
+    /// Write the configuration as INI text.
+    fn format(&self, f: &mut impl fmt::Write, options: &WriteOptions)
+    -> fmt::Result {
+        let mut groups: Vec<_> = self.groups().collect();
+        if options.sorted {
+            // DEFAULT remains first.
+            let start = groups.iter().take_while(|g| **g == "DEFAULT").count();
+            groups[start..].sort();
+        }
+
+        let header = |group: &str| if group == "DEFAULT" {
+            self.options.default_group_name().to_string()
+        } else {
+            group.to_string()
         };
 
-        let mut file = File::create(path)?;
+        for group in groups {
+            match self.parents.get(group) {
+                Some(parent) =>
+                    writeln!(f, "[{} : {}]", header(group), header(parent))?,
+                None => writeln!(f, "[{}]", header(group))?,
+            }
+
+            let mut vars: Vec<_> = self.variables_in_group(group).collect();
+            if options.sorted { vars.sort(); }
 
-        for group in self.groups() {
-            writeln!(file, "[{}]", group)?;
+            for var in vars {
+                let value = self.value(group, var)
+                    .expect("Listed variables must be set");
 
-            for var in self.variables_in_group(group) {
-                writeln!(
-                    file,
-                    "{} = {}",
-                    var,
-                    self[(group.as_str(), var.as_str())]
-                )?;
+                // Lists of repeated assignments are written back repeated.
+                let vals = if value.list.is_empty() {
+                    std::slice::from_ref(&value.val)
+                } else {
+                    value.list.as_slice()
+                };
+
+                for val in vals {
+                    // Continuation lines are indented so that they are read
+                    // back as part of the value.
+                    let val = quote::quote(val, &self.options)
+                        .replace('\n', "\n    ");
+
+                    let sep = self.options.separator();
+                    writeln!(f, "{} {} {}", var, sep, val)?;
+                }
             }
         }
 
@@ -209,9 +689,14 @@ impl Config {
     /// Merge two [Config]s, consuming both of the originals.
     ///
     /// Any duplicate variables will contain the values in `other`.
+    /// A group's parent in `other` replaces its parent in `self`.
     pub fn merge_with(mut self, other: Self) -> Self {
-        for (k, v) in other.values {
-            self.values.insert(k, v);
+        for ((group, var), v) in other.values {
+            let key = self.key(&group, &var);
+            self.values.insert(key, v);
+        }
+        for (group, parent) in other.parents {
+            self = self.set_parent(&group, &parent);
         }
         self
     }
@@ -220,11 +705,10 @@ impl Config {
     ///
     /// `set` can be used to create default settings by setting values prior to
     /// reading a configuration.
+    ///
+    /// To modify a configuration in place, use [Config::insert].
     pub fn set(mut self, group: &str, var: &str, val: &str) -> Self {
-        self.values.insert(
-            (group.into(), var.into()),
-            val.into()
-        );
+        self.insert(group, var, val);
         self
     }
 
@@ -235,40 +719,334 @@ impl Config {
         self.set("DEFAULT", var, val)
     }
 
+    /// Set the specified variable, returning its previous value if it was
+    /// set in the group.
+    ///
+    /// A variable that is already set keeps its position in the group.
+    pub fn insert(&mut self, group: &str, variable: &str, val: &str)
+    -> Option<String> {
+        let key = self.key(group, variable);
+
+        self.values.insert(key, Value::new(val.into(), Origin::Set))
+            .map(|v| v.val)
+    }
+
+    /// Remove the specified variable from the group, returning its value if it
+    /// was set.
+    ///
+    /// Values inherited from other groups are not affected.
+    pub fn remove(&mut self, group: &str, variable: &str) -> Option<String> {
+        self.values.remove(&self.key(group, variable)).map(|v| v.val)
+    }
+
+    /// Remove every variable in the specified group, and the group's parent.
+    ///
+    /// Returns `true` if the group was present.
+    pub fn remove_group(&mut self, group: &str) -> bool {
+        let group = self.key(group, "").0;
+        let count = self.values.entries.len();
+
+        self.values.retain(|k, _| k.0 != group);
+
+        let had_parent = self.parents.remove(&group).is_some();
+        self.values.entries.len() != count || had_parent
+    }
+
+    /// Rename the specified group, returning `true` if it was present.
+    ///
+    /// If a group named `to` already exists, the renamed group's variables
+    /// replace any of the same name in it. Groups inheriting from the renamed
+    /// group inherit from its new name.
+    pub fn rename_group(&mut self, from: &str, to: &str) -> bool {
+        let from = self.key(from, "").0;
+        let to = self.key(to, "").0;
+
+        let present = self.values.iter().any(|(k, _)| k.0 == from)
+            || self.parents.contains_key(&from);
+        if ! present || from == to { return present; }
+
+        let renamed: Vec<String> = self.values.iter()
+            .filter(|(k, _)| k.0 == from)
+            .map(|(k, _)| k.1.clone())
+            .collect();
+        self.values.retain(|k, _| k.0 != to || ! renamed.contains(&k.1));
+
+        for ((group, _), _) in &mut self.values.entries {
+            if *group == from { *group = to.clone(); }
+        }
+        self.values.reindex();
+
+        if let Some(parent) = self.parents.remove(&from) {
+            self.parents.insert(to.clone(), parent);
+        }
+        for parent in self.parents.values_mut() {
+            if *parent == from { *parent = to.clone(); }
+        }
+        true
+    }
+
+    /// Keep only the variables for which `f` returns `true`.
+    ///
+    /// `f` receives the group name, variable name, and value of each variable.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&str, &str, &str) -> bool,
+    {
+        self.values.retain(|(group, var), value| f(group, var, &value.val));
+    }
+
     /// Get the list of groups in the configuration file.
+    ///
+    /// The DEFAULT group is listed first, followed by the other groups in the
+    /// order they were first set.
     pub fn groups(&self) -> impl Iterator<Item = &String> {
-        self.values.keys().uniq().map(|k| &k.0)
+        let default = self.values.iter()
+            .map(|(k, _)| &k.0)
+            .find(|g| *g == "DEFAULT");
+
+        default.into_iter()
+            .chain(
+                self.values.iter()
+                    .map(|(k, _)| &k.0)
+                    .uniq()
+                    .filter(|g| *g != "DEFAULT")
+            )
     }
 
-    /// Get the list of variables set in the specified group.
+    /// Get the list of variables set in the specified group, in the order they
+    /// were first set.
     pub fn variables_in_group<'a>(&'a self, group: &'a str)
     -> impl Iterator<Item = &'a String> {
-        self.values.keys()
-            .filter_map(move |k| {
+        let group = self.key(group, "").0;
+
+        self.values.iter()
+            .filter_map(move |(k, _)| {
                 if k.0 == group { Some(&k.1) } else { None }
             })
     }
 
     pub fn group_by_key_value<'a>(&'a self, group: &'a str) ->
-        impl Iterator + 'a + Iterator<Item = (String, &String)>
+        impl Iterator + 'a + Iterator<Item = (String, &'a String)>
     {
-        self.variables_in_group(group)
-            .map(move |v| self.values.get_key_value(
-                &(group.to_owned(), v.to_owned())).unwrap()
-            )
-            .map(|(k, v)| (k.1.to_owned(), v))
+        let group = self.key(group, "").0;
+
+        self.values.iter()
+            .filter(move |(k, _)| k.0 == group)
+            .map(|(k, v)| (k.1.to_owned(), &v.val))
     }
 
     /// Retrieve the value of the specified variable within the DEFAULT group,
     /// or `None` if it is not set.
     pub fn get_default(&self, variable: &str) -> Option<&String> {
-        self.values.get(&("DEFAULT".into(), variable.into()))
+        self.get("DEFAULT", variable)
     }
 
     /// Retrieve the value of the specified variable within the specified group,
     /// or `None` if it is not set.
+    ///
+    /// The value may be inherited from another group; see [Config::resolve].
     pub fn get(&self, group: &str, variable: &str) -> Option<&String> {
-        self.values.get(&(group.into(), variable.into()))
+        self.value(group, variable).map(|v| &v.val)
+    }
+
+    /// Retrieve the value of the specified variable within the DEFAULT group,
+    /// parsed as a `T`.
+    ///
+    /// See [Config::get_as] for more information.
+    pub fn get_default_as<T>(&self, variable: &str)
+    -> Result<Option<T>, ValueError>
+        where T: FromStr,
+              T::Err: fmt::Display,
+    {
+        self.get_as("DEFAULT", variable)
+    }
+
+    /// Retrieve the value of the specified variable within the specified group,
+    /// parsed as a `T`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` if the variable is not set, or a [ValueError]
+    /// describing the value and its [Origin] if it cannot be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let conf = Config::default()
+    ///     .set("Server", "port", "8080");
+    ///
+    /// let port: u16 = conf.get_as("Server", "port").unwrap().unwrap();
+    /// assert_eq!(port, 8080);
+    /// ```
+    pub fn get_as<T>(&self, group: &str, variable: &str)
+    -> Result<Option<T>, ValueError>
+        where T: FromStr,
+              T::Err: fmt::Display,
+    {
+        let value = match self.value(group, variable) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        value.val.parse::<T>()
+            .map(Some)
+            .map_err(|e| ValueError {
+                group: group.into(),
+                variable: variable.into(),
+                value: value.val.clone(),
+                type_name: std::any::type_name::<T>(),
+                msg: e.to_string(),
+                origin: Box::new(value.origin.clone()),
+            })
+    }
+
+    /// Retrieve the value of the specified variable within the specified group,
+    /// parsed as a `T`, or `default` if the variable is not set.
+    ///
+    /// See [Config::get_as] for more information.
+    pub fn get_as_or<T>(&self, group: &str, variable: &str, default: T)
+    -> Result<T, ValueError>
+        where T: FromStr,
+              T::Err: fmt::Display,
+    {
+        self.get_as(group, variable).map(|v| v.unwrap_or(default))
+    }
+
+    /// Retrieve the [Origin] of the specified variable within the specified
+    /// group, or `None` if it is not set.
+    pub fn origin(&self, group: &str, variable: &str) -> Option<&Origin> {
+        self.value(group, variable).map(|v| &v.origin)
+    }
+
+    /// Make `group` inherit the variables it does not set from `parent`.
+    ///
+    /// Lookups via [Config::get] and similar methods search the group, then
+    /// its parent, then the parent's parent, and so on. The parent is written
+    /// in the group's header as `[group : parent]`; see
+    /// [ParserOptions::inheritance] to read it back.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let conf = Config::default()
+    ///     .set("base", "port", "80")
+    ///     .set("site", "host", "example.com")
+    ///     .set_parent("site", "base");
+    ///
+    /// assert_eq!(conf[("site", "port")], "80");
+    /// assert_eq!(conf.resolve("site", "port").unwrap(), "base");
+    /// assert_eq!(conf.resolve("site", "host").unwrap(), "site");
+    /// ```
+    pub fn set_parent(mut self, group: &str, parent: &str) -> Self {
+        let group = self.options.group_name(group);
+        let parent = self.options.group_name(parent);
+
+        self.parents.insert(group, parent);
+        self
+    }
+
+    /// Get the name of the group that the specified group inherits from, if
+    /// any.
+    pub fn parent(&self, group: &str) -> Option<&String> {
+        self.parents.get(&self.options.group_name(group))
+    }
+
+    /// Get the name of the group that supplies the value of the specified
+    /// variable, or `None` if it is not set.
+    ///
+    /// This is `group` itself unless the value is inherited from a parent
+    /// group or, with [ParserOptions::default_fallback], the DEFAULT group.
+    pub fn resolve(&self, group: &str, variable: &str) -> Option<&String> {
+        self.lookup(group, variable).map(|((group, _), _)| group)
+    }
+
+    /// Look up the [Value] of the specified variable.
+    fn value(&self, group: &str, variable: &str) -> Option<&Value> {
+        self.lookup(group, variable).map(|(_, value)| value)
+    }
+
+    /// Find the entry that supplies the value of the specified variable,
+    /// searching the group's ancestors and, if enabled, the DEFAULT group.
+    fn lookup(&self, group: &str, variable: &str) -> Option<&(Key, Value)> {
+        let mut key = self.key(group, variable);
+        let mut searched = vec![];
+
+        loop {
+            if let Some(entry) = self.values.get_key_value(&key) {
+                return Some(entry);
+            }
+
+            searched.push(key.0.clone());
+
+            // Stop at the root or if the groups inherit in a cycle.
+            match self.parents.get(&key.0) {
+                Some(parent) if ! searched.contains(parent) =>
+                    key.0 = parent.clone(),
+                _ => break,
+            }
+        }
+
+        let searched_default = searched.iter().any(|g| g == "DEFAULT");
+
+        if self.options.default_fallback && ! searched_default {
+            key.0 = "DEFAULT".into();
+            self.values.get_key_value(&key)
+        } else {
+            None
+        }
+    }
+
+    /// Build the [Key] for the specified variable, converting its names to the
+    /// case used by this configuration.
+    ///
+    /// The group named by [ParserOptions::default_group] is the DEFAULT group.
+    fn key(&self, group: &str, variable: &str) -> Key {
+        (self.options.group_name(group), self.options.fold(variable))
+    }
+}
+
+/// Format the configuration as INI text, as written by
+/// [Config::write_to_file_blocking].
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.format(f, &WriteOptions::default())
+    }
+}
+
+#[cfg(feature = "config_tokio")]
+/// Run the blocking function `f` on tokio's blocking thread pool, returning its
+/// result.
+///
+/// `path` is used to report an error if the task is cancelled.
+async fn spawn_blocking_tokio<T, F>(path: &Path, f: F) -> Result<T, FileError>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await
+        .map_err(|e| {
+            if e.is_panic() { std::panic::resume_unwind(e.into_panic()); }
+            FileError::IO((path.into(), io::ErrorKind::Interrupted))
+        })
+}
+
+/// Parse a [Config] from INI text.
+///
+/// Values and errors are reported as originating in a file named `<string>`;
+/// use [Config::from_reader] to provide a different name.
+///
+/// # Example
+///
+/// ```
+/// # use utility_belt::config::Config;
+/// let conf: Config = include_str!("../tests/test.ini").parse().unwrap();
+/// assert_eq!(conf["var1"], "val1");
+/// ```
+impl FromStr for Config {
+    type Err = Vec<FileError>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_reader(s.as_bytes(), "<string>")
     }
 }
 
@@ -285,7 +1063,48 @@ impl Index<(&str, &str)> for Config
     type Output = String;
 
     fn index(&self, key: (&str, &str)) -> &Self::Output {
-        &self.values[&(key.0.into(), key.1.into())]
+        self.get(key.0, key.1).expect("Configuration variable is not set")
+    }
+}
+
+/// Options that control how a [Config] is written.
+///
+/// # Example
+///
+/// ```
+/// # use utility_belt::config::{Config, WriteOptions};
+/// let conf = Config::default()
+///     .set("B", "var", "1")
+///     .set("A", "var", "2");
+///
+/// let text = conf.to_string_with(&WriteOptions::new().sorted(true));
+/// assert_eq!(text, "[A]\nvar = 2\n[B]\nvar = 1\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    sorted: bool,
+    backup: bool,
+}
+
+impl WriteOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sort groups and variables alphabetically rather than writing them in
+    /// the order they were set; the DEFAULT group is always written first.
+    pub fn sorted(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+
+    /// When writing to a file that already exists, keep its previous contents
+    /// in a file of the same name with `.bak` appended, replacing any earlier
+    /// backup.
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
     }
 }
 
@@ -295,6 +1114,12 @@ pub enum FileError {
     #[allow(clippy::upper_case_acronyms)]
     IO((PathBuf, io::ErrorKind)),
     Parse { file: PathBuf, msg: String, data: String, line: u32 },
+    /// A malformed command-line argument; `index` is the argument's position
+    /// in the list passed to [Config::merge_args].
+    Argument { msg: String, data: String, index: usize },
+    /// An included file could not be read; `chain` lists the files from the
+    /// one originally read to the problematic include.
+    Include { msg: String, chain: Vec<PathBuf> },
 }
 
 impl fmt::Display for FileError {
@@ -305,6 +1130,16 @@ impl fmt::Display for FileError {
             FileError::Parse { ref file, ref msg, ref data, ref line } =>
                 write!(f, "{} at line {} in {}:\n\t{}"
                     , msg, line, file.to_string_lossy(), data),
+            FileError::Argument { ref msg, ref data, ref index } =>
+                write!(f, "{} in argument {}:\n\t{}", msg, index, data),
+            FileError::Include { ref msg, ref chain } => {
+                write!(f, "{}: ", msg)?;
+                for (i, file) in chain.iter().enumerate() {
+                    if i > 0 { write!(f, " -> ")?; }
+                    write!(f, "{}", file.to_string_lossy())?;
+                }
+                Ok(())
+            },
         }
     }
 }
@@ -317,11 +1152,46 @@ impl From<io::Error> for FileError {
     }
 }
 
+/// Error returned when a configuration value cannot be parsed into the
+/// requested type.
+#[derive(Debug, Clone)]
+pub struct ValueError {
+    /// The group containing the variable.
+    pub group: String,
+    /// The name of the variable.
+    pub variable: String,
+    /// The raw value that failed to parse.
+    pub value: String,
+    /// The name of the type we attempted to parse.
+    pub type_name: &'static str,
+    /// The parser's description of the failure.
+    pub msg: String,
+    /// Where the value was set.
+    pub origin: Box<Origin>,
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot parse {:?} as {} for variable '{}' in group '{}' \
+            ({}): {}",
+            self.value, self.type_name, self.variable, self.group,
+            self.origin, self.msg)
+    }
+}
+
+impl std::error::Error for ValueError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
 
+    /// Held by every test that reads or modifies the process environment, as
+    /// such tests must not run concurrently.
+    pub(super) static ENV_LOCK: std::sync::Mutex<()> =
+        std::sync::Mutex::new(());
+
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn parse_variables() {
         let conf = Config::read_from_file(Path::new("tests/test.ini"))
@@ -332,6 +1202,7 @@ mod tests {
         assert_eq!(conf[("Group A", "var 3")], "value = three");
     }
 
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn merge_configs() {
         let conf = Config::read_from_file(Path::new("tests/test.ini"))
@@ -346,6 +1217,7 @@ mod tests {
         assert_eq!(conf[("Group A", "var 3")], "value = four");
     }
 
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn test_write_to_file() {
         use async_std::fs::remove_file;
@@ -361,7 +1233,7 @@ mod tests {
             .set_default("var1", "value")
             .set("Some Group", "my variable", "my value");
 
-        conf.write_to_file(&path).unwrap();
+        conf.write_to_file(&path).await.unwrap();
         println!("* conf: {:?}", conf);
 
         let read_conf = Config::read_from_file(&path).await.unwrap();
@@ -374,6 +1246,107 @@ mod tests {
         let _ = remove_file(&path).await;
     }
 
+    /// A configuration of several hundred kilobytes, with values identified
+    /// by `tag`.
+    #[cfg(any(feature = "config_async_std", feature = "config_tokio"))]
+    fn large_config(tag: usize) -> Config {
+        let mut conf = Config::default();
+        for g in 0..50 {
+            for v in 0..200 {
+                let val = format!("value {} of {}\n  continued {}", v, g, tag);
+                conf = conf.set(
+                    &format!("Group {}", g), &format!("var{}", v), &val
+                );
+            }
+        }
+        conf
+    }
+
+    /// Check that `dir` contains only `file`, which holds one of `configs`.
+    #[cfg(any(feature = "config_async_std", feature = "config_tokio"))]
+    fn check_written(dir: &Path, file: &str, configs: &[Config]) {
+        let names: Vec<_> = std::fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![file]);
+
+        let text = std::fs::read_to_string(dir.join(file)).unwrap();
+        assert!(configs.iter().any(|c| c.to_string() == text));
+    }
+
+    #[cfg(feature = "config_async_std")]
+    #[async_std::test]
+    async fn write_large_files_concurrently() {
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir()
+            .join(format!("ub_async_write_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("large.ini");
+
+        let configs: Arc<Vec<_>> =
+            Arc::new((0..8).map(large_config).collect());
+
+        configs[0].write_to_file(&path).await.unwrap();
+        let read = Config::read_from_file(&path).await.unwrap();
+        assert_eq!(read.to_string(), configs[0].to_string());
+
+        let tasks: Vec<_> = (0..configs.len())
+            .map(|i| {
+                let configs = configs.clone();
+                let path = path.clone();
+                async_std::task::spawn(async move {
+                    configs[i].write_to_file(&path).await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        check_written(&dir, "large.ini", &configs);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "config_tokio")]
+    #[tokio::test]
+    async fn write_large_files_concurrently_tokio() {
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir()
+            .join(format!("ub_tokio_write_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("large.ini");
+
+        let configs: Arc<Vec<_>> =
+            Arc::new((0..8).map(large_config).collect());
+
+        configs[0].write_to_file_tokio(&path).await.unwrap();
+        let read = Config::read_from_file_tokio(&path).await.unwrap();
+        assert_eq!(read.to_string(), configs[0].to_string());
+
+        let tasks: Vec<_> = (0..configs.len())
+            .map(|i| {
+                let configs = configs.clone();
+                let path = path.clone();
+                tokio::spawn(async move {
+                    configs[i].write_to_file_tokio(&path).await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        check_written(&dir, "large.ini", &configs);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn nonexistent_file_is_err() {
         let conf = Config::read_from_file(Path::new("nopath/notexist.conf"))
@@ -381,6 +1354,7 @@ mod tests {
         assert!(conf.is_err());
     }
 
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn get_default_group() {
         let conf = Config::read_from_file(Path::new("tests/test.ini"))
@@ -390,6 +1364,7 @@ mod tests {
         assert_eq!(conf.get_default("nothing"), None);
     }
 
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn get_group() {
         let conf = Config::read_from_file(Path::new("tests/test.ini"))
@@ -399,6 +1374,7 @@ mod tests {
         assert_eq!(conf.get("Group A", "var1"), None);
     }
 
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn get_nonexistent_group_is_none() {
         let conf = Config::read_from_file(Path::new("tests/test.ini"))
@@ -407,6 +1383,7 @@ mod tests {
         assert!(conf.get("Not a group", "var1").is_none());
     }
 
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn set_default_values() {
         let conf = Config::default()
@@ -421,6 +1398,468 @@ mod tests {
         assert_eq!(conf["some-var"], "my-value");
     }
 
+    #[cfg(feature = "config_async_std")]
+    #[async_std::test]
+    async fn get_typed_values() {
+        let conf = Config::read_from_file(Path::new("tests/typed.ini"))
+            .await.unwrap();
+
+        assert_eq!(conf.get_default_as::<u16>("port").unwrap(), Some(8080));
+        assert_eq!(conf.get_as::<bool>("Server", "enabled").unwrap(),
+            Some(true));
+        assert_eq!(conf.get_as::<u32>("Server", "missing").unwrap(), None);
+        assert_eq!(conf.get_as_or("Server", "missing", 5u8).unwrap(), 5);
+    }
+
+    #[cfg(feature = "config_async_std")]
+    #[async_std::test]
+    async fn typed_value_error_has_origin() {
+        let conf = Config::read_from_file(Path::new("tests/typed.ini"))
+            .await.unwrap();
+
+        let err = conf.get_default_as::<u32>("timeout").unwrap_err();
+        assert_eq!(err.group, "DEFAULT");
+        assert_eq!(err.variable, "timeout");
+        assert_eq!(err.value, "soon");
+        assert_eq!(err.type_name, "u32");
+        assert_eq!(*err.origin, Origin::File {
+            path: PathBuf::from("tests/typed.ini"),
+            line: 3,
+        });
+        assert!(err.to_string().contains("line 3 in tests/typed.ini"));
+    }
+
+    #[test]
+    fn set_values_have_set_origin() {
+        let conf = Config::default().set_default("port", "eighty");
+
+        assert_eq!(conf.origin("DEFAULT", "port"), Some(&Origin::Set));
+        assert_eq!(
+            *conf.get_default_as::<u16>("port").unwrap_err().origin,
+            Origin::Set
+        );
+    }
+
+    #[test]
+    fn read_file_blocking() {
+        let conf = Config::read_from_file_blocking(Path::new("tests/test.ini"))
+            .unwrap();
+
+        assert_eq!(conf[("DEFAULT", "var1")], "val1");
+        assert_eq!(conf[("Group A", "var 3")], "value = three");
+        assert_eq!(conf.origin("Group A", "var2"), Some(&Origin::File {
+            path: PathBuf::from("tests/test.ini"),
+            line: 5,
+        }));
+    }
+
+    #[test]
+    fn nonexistent_file_blocking_is_err() {
+        let path = Path::new("nopath/notexist.conf");
+        let errs = Config::read_from_file_blocking(path).unwrap_err();
+
+        match &errs[..] {
+            [FileError::IO((file, io::ErrorKind::NotFound))] =>
+                assert_eq!(file, path),
+            _ => panic!("Expected a single FileError::IO"),
+        }
+    }
+
+    #[test]
+    fn collect_all_parse_errors_blocking() {
+        let errs = Config::read_from_file_blocking(
+            Path::new("tests/invalid.ini")
+        ).unwrap_err();
+
+        assert_eq!(errs.len(), 4);
+        assert!(errs.iter().all(|e| matches!(e, FileError::Parse { .. })));
+    }
+
+    #[test]
+    fn parse_from_str() {
+        let conf = Config::from_str(include_str!("../tests/test.ini"))
+            .unwrap();
+
+        assert_eq!(conf["var1"], "val1");
+        assert_eq!(conf[("Group A", "var 3")], "value = three");
+        assert_eq!(conf.origin("DEFAULT", "var1"), Some(&Origin::File {
+            path: PathBuf::from("<string>"),
+            line: 2,
+        }));
+    }
+
+    #[test]
+    fn reader_errors_name_source() {
+        let data = include_bytes!("../tests/invalid.ini");
+        let errs = Config::from_reader(&data[..], "fixture").unwrap_err();
+
+        assert_eq!(errs.len(), 4);
+        match &errs[0] {
+            FileError::Parse { file, data, line, .. } => {
+                assert_eq!(file, Path::new("fixture"));
+                assert_eq!(data, "some variable");
+                assert_eq!(*line, 3);
+            },
+            _ => panic!("Expected a FileError::Parse"),
+        }
+    }
+
+    #[cfg(feature = "config_async_std")]
+    #[async_std::test]
+    async fn parse_from_async_reader() {
+        let data = include_bytes!("../tests/test.ini");
+        let conf = Config::from_async_reader(&data[..], "fixture")
+            .await.unwrap();
+        assert_eq!(conf[("Group A", "var2")], "value two");
+
+        let data = include_bytes!("../tests/invalid.ini");
+        let errs = Config::from_async_reader(&data[..], "fixture")
+            .await.unwrap_err();
+        assert_eq!(errs.len(), 4);
+    }
+
+    #[cfg(feature = "config_tokio")]
+    #[tokio::test]
+    async fn parse_from_tokio_reader() {
+        let data = include_bytes!("../tests/test.ini");
+        let conf = Config::from_tokio_reader(&data[..], "fixture")
+            .await.unwrap();
+        assert_eq!(conf[("Group A", "var2")], "value two");
+
+        let data = include_bytes!("../tests/invalid.ini");
+        let errs = Config::from_tokio_reader(&data[..], "fixture")
+            .await.unwrap_err();
+        assert_eq!(errs.len(), 4);
+    }
+
+    #[cfg(feature = "config_tokio")]
+    #[tokio::test]
+    async fn read_file_tokio() {
+        let conf = Config::read_from_file_tokio(Path::new("tests/test.ini"))
+            .await.unwrap();
+
+        assert_eq!(conf[("Group A", "var2")], "value two");
+        assert!(
+            Config::read_from_file_tokio(Path::new("nopath/notexist.conf"))
+                .await.is_err()
+        );
+    }
+
+    #[test]
+    fn groups_and_variables_keep_order() {
+        let conf: Config = "\
+            [Group B]\n\
+            z = 1\n\
+            a = 2\n\
+            [Group A]\n\
+            m = 3\n\
+            [DEFAULT]\n\
+            var = 4\n\
+            [Group B]\n\
+            b = 5\n\
+            z = 6\n\
+        ".parse().unwrap();
+
+        assert_eq!(
+            conf.groups().collect::<Vec<_>>(),
+            vec!["DEFAULT", "Group B", "Group A"]
+        );
+        assert_eq!(
+            conf.variables_in_group("Group B").collect::<Vec<_>>(),
+            vec!["z", "a", "b"]
+        );
+        assert_eq!(conf[("Group B", "z")], "6");
+        assert_eq!(
+            conf.to_string(),
+            "[DEFAULT]\nvar = 4\n[Group B]\nz = 1\nz = 6\na = 2\nb = 5\n\
+             [Group A]\nm = 3\n"
+        );
+    }
+
+    #[test]
+    fn merge_appends_new_variables() {
+        let conf = Config::default()
+            .set("Group", "b", "1")
+            .set("Group", "a", "2")
+            .merge_with(
+                Config::default()
+                    .set("Group", "c", "3")
+                    .set("Group", "b", "4")
+            );
+
+        assert_eq!(conf.to_string(), "[Group]\nb = 4\na = 2\nc = 3\n");
+    }
+
+    #[test]
+    fn write_sorted() {
+        let conf = Config::default()
+            .set("Group B", "z", "1")
+            .set("Group B", "a", "2")
+            .set("Group A", "m", "3")
+            .set_default("var", "4");
+
+        let path = std::env::temp_dir().join("writing_sorted_config_file.txt");
+        conf.write_to_file_blocking_with(
+            &path, &WriteOptions::new().sorted(true)
+        ).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[DEFAULT]\nvar = 4\n[Group A]\nm = 3\n[Group B]\na = 2\nz = 1\n"
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn parse_continuation_lines() {
+        let text = "\
+[SQL]
+query = SELECT *
+    FROM table
+\tWHERE id = 1
+  other = value
+
+pem =
+  -----BEGIN-----
+  abc
+  -----END-----
+next = 1
+";
+        let conf: Config = text.parse().unwrap();
+
+        assert_eq!(
+            conf[("SQL", "query")],
+            "SELECT *\nFROM table\nWHERE id = 1\nother = value"
+        );
+        assert_eq!(conf[("SQL", "pem")], "-----BEGIN-----\nabc\n-----END-----");
+        assert_eq!(conf[("SQL", "next")], "1");
+        assert_eq!(
+            conf.origin("SQL", "next"),
+            Some(&Origin::File { path: "<string>".into(), line: 11 })
+        );
+
+        // Consistently indented assignments are not continuations.
+        let conf: Config = "  a = 1\n  b = 2\n".parse().unwrap();
+        assert_eq!(conf["a"], "1");
+        assert_eq!(conf["b"], "2");
+    }
+
+    #[test]
+    fn multi_line_values_round_trip() {
+        let conf = Config::default()
+            .set("Group", "lines", "one\ntwo\nthree")
+            .set("Group", "after", "x");
+
+        let text = conf.to_string();
+        assert_eq!(
+            text,
+            "[Group]\nlines = one\n    two\n    three\nafter = x\n"
+        );
+
+        let read: Config = text.parse().unwrap();
+        assert_eq!(read[("Group", "lines")], "one\ntwo\nthree");
+        assert_eq!(read[("Group", "after")], "x");
+    }
+
+    #[test]
+    fn parse_quoted_values() {
+        let text = "\
+padded = \"  a b  \"
+escaped = \"tab\\there\\nline \\u{263A}\"   
+    not = continued
+plain = half \"quoted\"
+";
+        let conf: Config = text.parse().unwrap();
+
+        assert_eq!(conf["padded"], "  a b  ");
+        assert_eq!(conf["escaped"], "tab\there\nline \u{263A}");
+        assert_eq!(conf["not"], "continued");
+        assert_eq!(conf["plain"], "half \"quoted\"");
+    }
+
+    #[test]
+    fn invalid_quoted_values_are_err() {
+        let errs = "a = \"open\nb = \"x\" y\n".parse::<Config>().unwrap_err();
+
+        match &errs[..] {
+            [
+                FileError::Parse { msg: m1, line: 1, .. },
+                FileError::Parse { msg: m2, line: 2, .. },
+            ] => {
+                assert_eq!(m1, "Missing closing quote");
+                assert_eq!(m2, "Unexpected text after closing quote");
+            },
+            _ => panic!("Expected two parse errors; got {:?}", errs),
+        }
+    }
+
+    #[test]
+    fn quoted_values_round_trip() {
+        let conf = Config::default()
+            .set_default("padded", " x ")
+            .set_default("comment", "; x")
+            .set_default("blank lines", "a\n\nb")
+            .set_default("plain", "a \"b\" c");
+
+        let text = conf.to_string();
+        assert_eq!(text, "\
+[DEFAULT]
+padded = \" x \"
+comment = \"; x\"
+blank lines = \"a\\n\\nb\"
+plain = a \"b\" c
+");
+
+        let read: Config = text.parse().unwrap();
+        for var in conf.variables_in_group("DEFAULT") {
+            assert_eq!(read[var.as_str()], conf[var.as_str()]);
+        }
+    }
+
+    #[test]
+    fn parser_options_are_kept() {
+        let options = ParserOptions::new()
+            .comment_prefixes(&["#"])
+            .separators(&[':'])
+            .case_sensitive(false);
+
+        let conf = Config::from_reader_with(
+            "[Server]\nHost: a # b\n".as_bytes(), "<string>", &options
+        ).unwrap();
+        assert_eq!(conf.parser_options(), &options);
+
+        let conf = conf
+            .merge_with(Config::default().set("SERVER", "Port", "80"))
+            .set("server", "HOST", "# c");
+
+        assert_eq!(
+            conf.variables_in_group("Server").collect::<Vec<_>>(),
+            vec!["host", "port"]
+        );
+        assert_eq!(conf.to_string(), "[server]\nhost : \"# c\"\nport : 80\n");
+
+        let conf = Config::default()
+            .set("A", "Var", "1")
+            .with_parser_options(options);
+        assert_eq!(conf[("a", "VAR")], "1");
+    }
+
+    #[test]
+    fn default_fallback() {
+        let text = "var1 = default\n[Group A]\nvar2 = a\n";
+
+        let conf: Config = text.parse().unwrap();
+        assert!(conf.get("Group A", "var1").is_none());
+
+        let options = ParserOptions::new().default_fallback(true);
+        let conf = Config::from_reader_with(
+            text.as_bytes(), "<string>", &options
+        ).unwrap();
+
+        assert_eq!(conf[("Group A", "var1")], "default");
+        assert_eq!(conf.resolve("Group A", "var1").unwrap(), "DEFAULT");
+        assert_eq!(conf.resolve("Group A", "var2").unwrap(), "Group A");
+        assert_eq!(
+            conf.origin("Group A", "var1"),
+            conf.origin("DEFAULT", "var1")
+        );
+        assert!(conf.get("Group A", "var3").is_none());
+        assert_eq!(
+            conf.variables_in_group("Group A").collect::<Vec<_>>(),
+            vec!["var2"]
+        );
+    }
+
+    #[test]
+    fn group_inheritance() {
+        let options = ParserOptions::new()
+            .inheritance(true)
+            .default_fallback(true);
+
+        let conf = Config::from_reader_with("\
+a = default
+[base]
+b = base
+c = base
+[site : base]
+c = site
+[Loop 1 : Loop 2]
+[Loop 2 : Loop 1]
+".as_bytes(), "<string>", &options).unwrap();
+
+        assert_eq!(conf.parent("site").unwrap(), "base");
+        assert_eq!(conf.resolve("site", "a").unwrap(), "DEFAULT");
+        assert_eq!(conf.resolve("site", "b").unwrap(), "base");
+        assert_eq!(conf.resolve("site", "c").unwrap(), "site");
+        assert_eq!(conf[("Loop 1", "a")], "default");
+        assert!(conf.get("Loop 2", "b").is_none());
+
+        assert_eq!(
+            conf.to_string(),
+            "[DEFAULT]\na = default\n[base]\nb = base\nc = base\n\
+                [site : base]\nc = site\n"
+        );
+
+        let conf: Config = "[site : base]\n".parse().unwrap();
+        assert!(conf.parent("site : base").is_none());
+
+        let errs = Config::from_reader_with(
+            "[site : ]\n".as_bytes(), "<string>", &options
+        ).unwrap_err();
+        assert!(matches!(
+            errs[0],
+            FileError::Parse { ref msg, line: 1, .. } if msg.contains("parent")
+        ));
+    }
+
+    #[test]
+    fn edit_in_place() {
+        let mut conf: Config = "a = 1\n[A]\nx = 1\ny = 2\n[B]\nz = 3\n"
+            .parse().unwrap();
+
+        assert_eq!(conf.insert("A", "x", "10"), Some("1".into()));
+        assert_eq!(conf.insert("A", "w", "0"), None);
+        assert_eq!(conf.origin("A", "x"), Some(&Origin::Set));
+
+        assert_eq!(conf.remove("A", "y"), Some("2".into()));
+        assert_eq!(conf.remove("A", "y"), None);
+
+        assert!(conf.remove_group("B"));
+        assert!(! conf.remove_group("B"));
+
+        conf.retain(|group, _, val| group != "DEFAULT" || val != "1");
+
+        assert_eq!(conf.to_string(), "[A]\nx = 10\nw = 0\n");
+    }
+
+    #[test]
+    fn rename_groups() {
+        let mut conf = Config::default()
+            .set("A", "x", "1")
+            .set("A", "y", "2")
+            .set("B", "y", "3")
+            .set("B", "z", "4")
+            .set("C", "v", "5")
+            .set_parent("C", "A")
+            .set_parent("A", "base");
+
+        assert!(! conf.rename_group("missing", "D"));
+        assert!(conf.rename_group("A", "B"));
+
+        assert_eq!(conf.groups().collect::<Vec<_>>(), vec!["B", "C"]);
+        assert_eq!(
+            conf.variables_in_group("B").collect::<Vec<_>>(),
+            vec!["x", "y", "z"]
+        );
+        assert_eq!(conf[("B", "y")], "2");
+        assert_eq!(conf[("C", "x")], "1");
+        assert_eq!(conf.parent("C").unwrap(), "B");
+        assert_eq!(conf.parent("B").unwrap(), "base");
+        assert!(conf.parent("A").is_none());
+    }
+
+    #[cfg(feature = "config_async_std")]
     #[async_std::test]
     async fn collect_all_parse_errors() {
         let conf = Config::read_from_file(Path::new("tests/invalid.ini")).await;
@@ -0,0 +1,177 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Override configuration values with command-line arguments
+
+use super::{Config, FileError, Origin, Value};
+
+
+impl Config {
+    /// Override configuration values with `--set` command-line arguments.
+    ///
+    /// Values are given as `--set group.var=value`, `--set group:var=value`,
+    /// or `--set=group.var=value`; if no group is given, the variable is set in
+    /// the DEFAULT group. If the key contains a colon, the group name ends at
+    /// the first colon; otherwise it ends at the first period. Any other
+    /// arguments are ignored, so the full command line can be passed.
+    ///
+    /// The arguments are merged as with [Config::merge_with], and each value
+    /// records the position of its argument as its [Origin].
+    ///
+    /// # Returns
+    ///
+    /// Returns the merged configuration, or a list of [FileError::Argument]s
+    /// describing every malformed argument.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let args = ["prog", "--set", "Group A:var 2=value", "--set=port=8080"];
+    ///
+    /// let conf = Config::default()
+    ///     .set_default("port", "80")
+    ///     .merge_args(args.iter())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(conf[("Group A", "var 2")], "value");
+    /// assert_eq!(conf["port"], "8080");
+    /// ```
+    pub fn merge_args<I, S>(self, args: I) -> Result<Self, Vec<FileError>>
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>,
+    {
+        let mut conf = Config::default();
+        let mut errors = vec![];
+        let mut args = args.into_iter().enumerate();
+
+        while let Some((index, arg)) = args.next() {
+            let arg = arg.as_ref();
+
+            let (index, setting) = if arg == "--set" {
+                match args.next() {
+                    Some((i, setting)) => (i, setting.as_ref().to_string()),
+                    None => {
+                        errors.push(FileError::Argument {
+                            msg: "Missing value for --set".into(),
+                            data: arg.into(),
+                            index,
+                        });
+                        break;
+                    },
+                }
+            } else if let Some(setting) = arg.strip_prefix("--set=") {
+                (index, setting.to_string())
+            } else {
+                continue;
+            };
+
+            match parse_setting(&setting) {
+                Ok((group, var, val)) => {
+                    conf.values.insert(
+                        (group.into(), var.into()),
+                        Value::new(val.into(), Origin::Arg { index })
+                    );
+                },
+                Err(msg) => errors.push(FileError::Argument {
+                    msg: msg.into(),
+                    data: setting,
+                    index,
+                }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(self.merge_with(conf))
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Split a `group.var=value` setting into its group, variable, and value.
+fn parse_setting(setting: &str) -> Result<(&str, &str, &str), &'static str> {
+    let (key, val) = setting.split_once('=')
+        .ok_or("Expected an assignment of the form group.var=value")?;
+
+    let (group, var) = match key.split_once(':') {
+        Some(pair) => pair,
+        None => key.split_once('.').unwrap_or(("DEFAULT", key)),
+    };
+
+    let (group, var) = (group.trim(), var.trim());
+
+    if group.is_empty() {
+        Err("Assignment requires a group name")
+    } else if var.is_empty() {
+        Err("Assignment requires a variable name")
+    } else {
+        Ok((group, var, val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn args_override_values() {
+        let conf = Config::default()
+            .set_default("port", "80")
+            .set("Server", "host", "localhost")
+            .merge_args([
+                "prog", "-v",
+                "--set", "Server.host=example.com",
+                "--set=Group A:var.2=a=b",
+                "--set", "port=8080",
+            ])
+            .unwrap();
+
+        assert_eq!(conf[("Server", "host")], "example.com");
+        assert_eq!(conf[("Group A", "var.2")], "a=b");
+        assert_eq!(conf["port"], "8080");
+        assert_eq!(
+            conf.origin("Server", "host"),
+            Some(&Origin::Arg { index: 3 })
+        );
+        assert_eq!(
+            conf.origin("Group A", "var.2"),
+            Some(&Origin::Arg { index: 4 })
+        );
+    }
+
+    #[test]
+    fn collect_all_argument_errors() {
+        let errs = Config::default()
+            .merge_args([
+                "--set", "novalue",
+                "--set=Group.=value",
+                "--set", ":var=value",
+                "--set=ok=1",
+                "--set",
+            ])
+            .unwrap_err();
+
+        let expected = [
+            ("assignment", "novalue", 1),
+            ("variable name", "Group.=value", 2),
+            ("group name", ":var=value", 4),
+            ("Missing value", "--set", 6),
+        ];
+
+        assert_eq!(errs.len(), expected.len());
+
+        for (err, expected) in errs.iter().zip(&expected) {
+            match err {
+                FileError::Argument { msg, data, index } => {
+                    assert!(msg.contains(expected.0));
+                    assert_eq!(data, expected.1);
+                    assert_eq!(*index, expected.2);
+                },
+                _ => panic!("Expected a FileError::Argument"),
+            }
+        }
+    }
+}
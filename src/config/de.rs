@@ -0,0 +1,889 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Deserialize a [Config] into user types
+//!
+//! Scalar fields of the top-level struct are read from the DEFAULT group;
+//! fields that are themselves structs or maps are read from the group of the
+//! same name. Use `#[serde(rename = "Group A")]` for group names that are not
+//! valid Rust identifiers.
+//!
+//! Deserialization does not stop at the first bad value; like
+//! [Config::read_from_file_blocking], every missing or mistyped variable is
+//! collected and reported at once.
+//!
+//! A variable that is not set is left to serde: `Option` fields become `None`,
+//! `#[serde(default)]` fields take their default value, and any other field is
+//! reported as [Error::Missing].
+//!
+//! # Example
+//!
+//! ```
+//! # use serde::Deserialize;
+//! # use utility_belt::config::{Config, from_config};
+//! #[derive(Deserialize)]
+//! struct Settings {
+//!     name: String,
+//!     server: Server,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Server {
+//!     port: u16,
+//!     timeout: Option<u32>,
+//! }
+//!
+//! let conf = Config::default()
+//!     .set_default("name", "my app")
+//!     .set("server", "port", "8080");
+//!
+//! let settings: Settings = from_config(&conf).unwrap();
+//! assert_eq!(settings.server.port, 8080);
+//! assert!(settings.server.timeout.is_none());
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+};
+
+use serde::{
+    de::{self, Deserialize, DeserializeSeed, IntoDeserializer, MapAccess,
+        Visitor},
+    forward_to_deserialize_any,
+};
+
+use super::{Config, Key, ValueError};
+
+
+/// Deserialize a `T` from the provided [Config].
+///
+/// # Returns
+///
+/// Returns the deserialized value, or a list of every error encountered.
+pub fn from_config<'de, T>(conf: &'de Config) -> Result<T, Vec<Error>>
+    where T: Deserialize<'de>,
+{
+    // Only the variables that are set are passed to serde, so a required
+    // variable that is not set stops deserialization. It is then passed as
+    // well on the next attempt, so that it is reported along with every other
+    // error.
+    let mut required = HashSet::new();
+
+    loop {
+        let errors = RefCell::new(vec![]);
+        let res = T::deserialize(ConfigDeserializer(Ctx {
+            conf,
+            errors: &errors,
+            required: &required,
+        }));
+
+        let mut errors = errors.into_inner();
+
+        match res {
+            Ok(v) if errors.is_empty() => return Ok(v),
+            Ok(_) => return Err(errors),
+            Err(Error::Missing { ref group, ref variable })
+                if required.insert((group.clone(), variable.clone())) => {},
+            Err(e) => {
+                errors.push(e);
+                return Err(errors);
+            },
+        }
+    }
+}
+
+/// Error for values that cannot be deserialized.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// A required variable is not set.
+    Missing { group: String, variable: String },
+    /// A variable's value cannot be parsed into the requested type.
+    Value(ValueError),
+    /// Any other error reported while deserializing.
+    Custom(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Missing { ref group, ref variable } =>
+                write!(f, "Missing variable '{}' in group '{}'",
+                    variable, group),
+            Error::Value(ref e) => e.fmt(f),
+            Error::Custom(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        // The group is filled in by the deserializer of the struct; see
+        // Error::in_group.
+        Error::Missing { group: String::new(), variable: field.into() }
+    }
+}
+
+impl Error {
+    /// Name the group of a missing field reported by serde.
+    fn in_group(self, name: &str) -> Self {
+        match self {
+            Error::Missing { group, variable } if group.is_empty() =>
+                Error::Missing { group: name.into(), variable },
+            e => e,
+        }
+    }
+}
+
+/// State shared by all of the deserializers for a single [from_config] call.
+///
+/// Errors that can be recovered from are recorded in `errors` and a
+/// placeholder value is returned so that deserialization can continue.
+#[derive(Clone, Copy)]
+struct Ctx<'a, 'de> {
+    conf: &'de Config,
+    errors: &'a RefCell<Vec<Error>>,
+    /// Fields that serde requires but that are not set, so that they are
+    /// reported as missing.
+    required: &'a HashSet<Key>,
+}
+
+impl<'a, 'de> Ctx<'a, 'de> {
+    fn record(&self, err: Error) {
+        self.errors.borrow_mut().push(err);
+    }
+
+    /// Determine whether `group` is set, comparing names as
+    /// [Config::get] does.
+    fn has_group(&self, group: &str) -> bool {
+        let group = self.conf.key(group, "").0;
+        self.conf.groups().any(|g| *g == group)
+    }
+
+    /// The fields of a struct to pass to serde: those that are set within
+    /// `group`, or in the DEFAULT group or as a group for the top-level
+    /// struct, along with those known to be required.
+    fn fields(&self, group: &str, fields: &[&'static str])
+    -> Vec<&'static str> {
+        let set = |field: &str| if group == "DEFAULT" {
+            self.has_group(field) || self.conf.get_default(field).is_some()
+        } else {
+            self.conf.get(group, field).is_some()
+        };
+
+        fields.iter()
+            .copied()
+            .filter(|f| {
+                set(f) || self.required.contains(&(group.into(), f.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// Deserializes the [Config] as a whole.
+struct ConfigDeserializer<'a, 'de>(Ctx<'a, 'de>);
+
+impl<'a, 'de> de::Deserializer<'de> for ConfigDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        let conf = self.0.conf;
+
+        let keys = conf.variables_in_group("DEFAULT")
+            .chain(conf.groups().filter(|g| *g != "DEFAULT"))
+            .map(|k| k.as_str())
+            .collect();
+
+        visitor.visit_map(Access::new(self.0, None, keys))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        let fields = self.0.fields("DEFAULT", fields);
+
+        visitor.visit_map(Access::new(self.0, None, fields))
+            .map_err(|e| e.in_group("DEFAULT"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Deserializes a single group of the [Config].
+struct GroupDeserializer<'a, 'de> {
+    ctx: Ctx<'a, 'de>,
+    group: &'de str,
+}
+
+impl<'a, 'de> de::Deserializer<'de> for GroupDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        let keys = self.ctx.conf.variables_in_group(self.group)
+            .map(|k| k.as_str())
+            .collect();
+
+        visitor.visit_map(Access::new(self.ctx, Some(self.group), keys))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        let fields = self.ctx.fields(self.group, fields);
+
+        visitor.visit_map(Access::new(self.ctx, Some(self.group), fields))
+            .map_err(|e| e.in_group(self.group))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Iterates over the variables of a group, or the fields of the top-level
+/// struct if `group` is `None`.
+struct Access<'a, 'de> {
+    ctx: Ctx<'a, 'de>,
+    group: Option<&'de str>,
+    keys: std::vec::IntoIter<&'de str>,
+    current: &'de str,
+}
+
+impl<'a, 'de> Access<'a, 'de> {
+    fn new(ctx: Ctx<'a, 'de>, group: Option<&'de str>, keys: Vec<&'de str>)
+    -> Self {
+        Self { ctx, group, keys: keys.into_iter(), current: "" }
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+        where K: DeserializeSeed<'de>,
+    {
+        match self.keys.next() {
+            Some(key) => {
+                self.current = key;
+                seed.deserialize(key.into_deserializer()).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+        where V: DeserializeSeed<'de>,
+    {
+        match self.group {
+            Some(group) => seed.deserialize(ValueDeserializer {
+                ctx: self.ctx,
+                group,
+                var: self.current,
+            }),
+            None => seed.deserialize(TopLevelDeserializer {
+                ctx: self.ctx,
+                name: self.current,
+            }),
+        }
+    }
+}
+
+/// Deserializes a field of the top-level struct, which may be either a
+/// variable in the DEFAULT group or an entire group.
+struct TopLevelDeserializer<'a, 'de> {
+    ctx: Ctx<'a, 'de>,
+    name: &'de str,
+}
+
+impl<'a, 'de> TopLevelDeserializer<'a, 'de> {
+    fn group(self) -> GroupDeserializer<'a, 'de> {
+        GroupDeserializer { ctx: self.ctx, group: self.name }
+    }
+
+    fn value(self) -> ValueDeserializer<'a, 'de> {
+        ValueDeserializer { ctx: self.ctx, group: "DEFAULT", var: self.name }
+    }
+}
+
+/// Forward deserializer methods to the [ValueDeserializer] for the DEFAULT
+/// group.
+macro_rules! forward_to_value {
+    ($($method:ident)*) => {$(
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where V: Visitor<'de>,
+        {
+            self.value().$method(visitor)
+        }
+    )*};
+}
+
+impl<'a, 'de> de::Deserializer<'de> for TopLevelDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        if self.ctx.has_group(self.name) {
+            self.group().deserialize_any(visitor)
+        } else {
+            self.value().deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        if self.ctx.has_group(self.name)
+            || self.ctx.conf.get_default(self.name).is_some()
+        {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.group().deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.group().deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V)
+    -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.value().deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V)
+    -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V)
+    -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.value().deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.value().deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.value().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_value! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16
+        deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit
+        deserialize_seq deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// Deserializes the value of a single variable.
+struct ValueDeserializer<'a, 'de> {
+    ctx: Ctx<'a, 'de>,
+    group: &'de str,
+    var: &'de str,
+}
+
+impl<'a, 'de> ValueDeserializer<'a, 'de> {
+    fn get(&self) -> Option<&'de String> {
+        self.ctx.conf.get(self.group, self.var)
+    }
+
+    fn missing(&self) -> Error {
+        Error::Missing {
+            group: self.group.into(),
+            variable: self.var.into(),
+        }
+    }
+
+    /// Parse the value into a `T`, recording any error and returning a
+    /// placeholder value in its place.
+    fn parse<T>(&self) -> T
+        where T: FromStr + Default,
+              T::Err: fmt::Display,
+    {
+        match self.ctx.conf.get_as::<T>(self.group, self.var) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                self.ctx.record(self.missing());
+                T::default()
+            },
+            Err(e) => {
+                self.ctx.record(Error::Value(e));
+                T::default()
+            },
+        }
+    }
+
+    fn unsupported(&self, kind: &str) -> Error {
+        Error::Custom(format!(
+            "Variable '{}' in group '{}' cannot be deserialized as a {}",
+            self.var, self.group, kind
+        ))
+    }
+}
+
+/// Implement deserializer methods for types parsed via [FromStr].
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {$(
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where V: Visitor<'de>,
+        {
+            visitor.$visit(self.parse())
+        }
+    )*};
+}
+
+impl<'a, 'de> de::Deserializer<'de> for ValueDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match self.get() {
+            Some(val) => visitor.visit_borrowed_str(val),
+            None => Err(self.missing()),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match self.get() {
+            Some(val) => visitor.visit_borrowed_str(val),
+            None => {
+                self.ctx.record(self.missing());
+                visitor.visit_borrowed_str("")
+            },
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match self.get() {
+            Some(val) => visitor.visit_borrowed_bytes(val.as_bytes()),
+            None => {
+                self.ctx.record(self.missing());
+                visitor.visit_borrowed_bytes(&[])
+            },
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match self.get() {
+            Some(_) => visitor.visit_some(self),
+            None => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V)
+    -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V)
+    -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        Err(self.unsupported("sequence"))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, _visitor: V)
+    -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        Err(self.unsupported("tuple"))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        _visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        Err(self.unsupported("tuple"))
+    }
+
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        Err(self.unsupported("map"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        Err(self.unsupported("struct"))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match self.get() {
+            Some(val) => visitor.visit_enum(val.as_str().into_deserializer()),
+            None => Err(self.missing()),
+        }
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, path::Path};
+    use serde::Deserialize;
+    use crate::config::ParserOptions;
+
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfig {
+        var1: String,
+        #[serde(rename = "Group A")]
+        group_a: GroupA,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct GroupA {
+        var2: String,
+        #[serde(rename = "var 3")]
+        var3: String,
+    }
+
+    #[test]
+    fn deserialize_struct_with_groups() {
+        let conf = Config::read_from_file_blocking(Path::new("tests/test.ini"))
+            .unwrap();
+
+        let test: TestConfig = from_config(&conf).unwrap();
+        assert_eq!(test.var1, "val1");
+        assert_eq!(test.group_a.var2, "value two");
+        assert_eq!(test.group_a.var3, "value = three");
+    }
+
+    #[test]
+    fn deserialize_typed_and_borrowed_values() {
+        #[derive(Deserialize)]
+        struct Settings<'a> {
+            name: &'a str,
+            verbose: bool,
+            level: Level,
+            server: Server,
+        }
+
+        #[derive(Deserialize)]
+        struct Server {
+            port: u16,
+            ratio: f32,
+            timeout: Option<u32>,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Level { Low, High }
+
+        let conf = Config::default()
+            .set_default("name", "app")
+            .set_default("verbose", "true")
+            .set_default("level", "High")
+            .set("server", "port", "8080")
+            .set("server", "ratio", "0.5");
+
+        let settings: Settings = from_config(&conf).unwrap();
+        assert_eq!(settings.name, "app");
+        assert!(settings.verbose);
+        assert_eq!(settings.level, Level::High);
+        assert_eq!(settings.server.port, 8080);
+        assert_eq!(settings.server.ratio, 0.5);
+        assert_eq!(settings.server.timeout, None);
+    }
+
+    #[test]
+    fn deserialize_maps() {
+        let conf = Config::default()
+            .set_default("var1", "a")
+            .set("Group", "var2", "b")
+            .set("Group", "var3", "c");
+
+        let group: HashMap<String, HashMap<String, String>> =
+            from_config(&Config::default().set("Group", "var2", "b"))
+                .unwrap();
+        assert_eq!(group["Group"]["var2"], "b");
+
+        #[derive(Deserialize)]
+        struct Settings {
+            var1: String,
+            #[serde(rename = "Group")]
+            group: HashMap<String, String>,
+        }
+
+        let settings: Settings = from_config(&conf).unwrap();
+        assert_eq!(settings.var1, "a");
+        assert_eq!(settings.group.len(), 2);
+        assert_eq!(settings.group["var3"], "c");
+    }
+
+    #[test]
+    fn defaults_for_missing_variables() {
+        fn default_port() -> u16 { 80 }
+
+        #[derive(Debug, Default, PartialEq, Deserialize)]
+        struct Server {
+            #[serde(default = "default_port")]
+            port: u16,
+            #[serde(default)]
+            host: String,
+            timeout: Option<u32>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Settings {
+            #[serde(default)]
+            verbose: bool,
+            name: String,
+            server: Server,
+            #[serde(default)]
+            cache: Server,
+        }
+
+        let conf = Config::default()
+            .set_default("name", "app")
+            .set("server", "host", "example.com");
+
+        let settings: Settings = from_config(&conf).unwrap();
+        assert!(! settings.verbose);
+        assert_eq!(settings.name, "app");
+        assert_eq!(settings.server, Server {
+            port: 80,
+            host: "example.com".into(),
+            timeout: None,
+        });
+        assert_eq!(settings.cache, Server::default());
+
+        let conf = Config::default().set("server", "port", "x");
+        let errs = from_config::<Settings>(&conf).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert!(matches!(
+            errs[0],
+            Error::Missing { ref group, ref variable }
+                if group == "DEFAULT" && variable == "name"
+        ));
+        assert!(matches!(errs[1], Error::Value(_)));
+    }
+
+    #[test]
+    fn case_insensitive_groups() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            port: u16,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Settings {
+            #[serde(rename = "Server")]
+            server: Option<Server>,
+            #[serde(rename = "Cache")]
+            cache: Option<Server>,
+        }
+
+        let options = ParserOptions::new().case_sensitive(false);
+        let conf = Config::from_reader_with(
+            "[SERVER]\nPort = 80\n".as_bytes(),
+            "<string>",
+            &options
+        ).unwrap();
+
+        let settings: Settings = from_config(&conf).unwrap();
+        assert_eq!(settings.server, Some(Server { port: 80 }));
+        assert_eq!(settings.cache, None);
+    }
+
+    #[test]
+    fn collect_all_deserialization_errors() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Settings {
+            name: String,
+            port: u16,
+            server: Server,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Server {
+            host: String,
+            timeout: u32,
+        }
+
+        let conf = Config::default()
+            .set_default("port", "http")
+            .set("server", "timeout", "-1");
+
+        let errs = from_config::<Settings>(&conf).unwrap_err();
+        assert_eq!(errs.len(), 4);
+
+        match &errs[0] {
+            Error::Missing { group, variable } => {
+                assert_eq!(group, "DEFAULT");
+                assert_eq!(variable, "name");
+            },
+            e => panic!("Expected Error::Missing; got {:?}", e),
+        }
+
+        match &errs[1] {
+            Error::Value(e) => {
+                assert_eq!(e.variable, "port");
+                assert_eq!(e.value, "http");
+            },
+            e => panic!("Expected Error::Value; got {:?}", e),
+        }
+
+        match &errs[2] {
+            Error::Missing { group, variable } => {
+                assert_eq!(group, "server");
+                assert_eq!(variable, "host");
+            },
+            e => panic!("Expected Error::Missing; got {:?}", e),
+        }
+
+        match &errs[3] {
+            Error::Value(e) => {
+                assert_eq!(e.group, "server");
+                assert_eq!(e.variable, "timeout");
+                assert_eq!(e.type_name, "u32");
+            },
+            e => panic!("Expected Error::Value; got {:?}", e),
+        }
+    }
+}
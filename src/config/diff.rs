@@ -0,0 +1,379 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Differences between two configurations
+//!
+//! [Config::diff] lists the variables added, removed, and changed between two
+//! configurations, grouped by group. The [ConfigDiff] can be displayed as
+//! text or rendered as JSON via [ConfigDiff::to_json].
+//!
+//! Only the variables set in each group are compared; values inherited from
+//! other groups are not.
+//!
+//! # Example
+//!
+//! ```
+//! # use utility_belt::config::Config;
+//! let old: Config = "[Server]\nport = 80\ndebug = true\n".parse().unwrap();
+//! let new: Config = "[Server]\nport = 8080\nhost = a\n".parse().unwrap();
+//!
+//! let diff = old.diff(&new);
+//! assert_eq!(
+//!     diff.to_string(),
+//!     "[Server]\n~ port = 80 -> 8080\n- debug = true\n+ host = a\n"
+//! );
+//! assert_eq!(
+//!     diff.to_json(),
+//!     concat!(
+//!         r#"{"Server":{"added":{"host":"a"},"removed":{"debug":"true"},"#,
+//!         r#""changed":{"port":{"old":"80","new":"8080"}}}}"#,
+//!     )
+//! );
+//! ```
+
+use std::fmt;
+
+use super::{
+    Config,
+    ParserOptions,
+    Value,
+    quote::{quote, quote_always},
+};
+
+
+/// The differences between two [Config]s.
+///
+/// See the [diff module](self) documentation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// The groups containing differences, in the order they appear in the
+    /// original configuration followed by those only in the new one.
+    pub groups: Vec<GroupDiff>,
+}
+
+/// The differences within a single group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDiff {
+    /// The name of the group.
+    pub name: String,
+    /// The variables that differ, in the order they appear in the original
+    /// configuration followed by those only in the new one.
+    pub changes: Vec<Change>,
+}
+
+/// A difference in a single variable.
+///
+/// Values of repeated variables are listed one per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The variable is only set in the new configuration.
+    Added { variable: String, value: String },
+    /// The variable is only set in the original configuration.
+    Removed { variable: String, value: String },
+    /// The variable's value differs.
+    Changed { variable: String, old: String, new: String },
+}
+
+impl Change {
+    /// The name of the variable that differs.
+    pub fn variable(&self) -> &str {
+        match self {
+            Change::Added { variable, .. }
+            | Change::Removed { variable, .. }
+            | Change::Changed { variable, .. } => variable,
+        }
+    }
+}
+
+impl Config {
+    /// List the differences between this configuration and `other`.
+    ///
+    /// Values in `other` are considered new.
+    pub fn diff(&self, other: &Config) -> ConfigDiff {
+        let mut groups: Vec<&String> = self.groups().collect();
+        for group in other.groups() {
+            if ! groups.contains(&group) { groups.push(group); }
+        }
+
+        let groups = groups.into_iter()
+            .map(|group| GroupDiff {
+                name: group.clone(),
+                changes: diff_group(self, other, group),
+            })
+            .filter(|g| ! g.changes.is_empty())
+            .collect();
+
+        ConfigDiff { groups }
+    }
+}
+
+/// List the differences within `group`.
+fn diff_group(old: &Config, new: &Config, group: &str) -> Vec<Change> {
+    let value = |conf: &Config, var: &String| {
+        let key = (group.to_string(), var.clone());
+        conf.values.get_key_value(&key).map(|(_, v)| text(v))
+    };
+
+    let mut changes = vec![];
+
+    for var in old.variables_in_group(group) {
+        let old_val = value(old, var).expect("Listed variables must be set");
+
+        match value(new, var) {
+            None => changes.push(Change::Removed {
+                variable: var.clone(),
+                value: old_val,
+            }),
+            Some(new_val) if new_val != old_val => changes.push(
+                Change::Changed {
+                    variable: var.clone(),
+                    old: old_val,
+                    new: new_val,
+                }
+            ),
+            Some(_) => {},
+        }
+    }
+
+    for var in new.variables_in_group(group) {
+        if value(old, var).is_none() {
+            changes.push(Change::Added {
+                variable: var.clone(),
+                value: value(new, var).expect("Listed variables must be set"),
+            });
+        }
+    }
+
+    changes
+}
+
+/// The text of a value, listing repeated values one per line.
+fn text(value: &Value) -> String {
+    if value.list.is_empty() {
+        value.val.clone()
+    } else {
+        value.list.join("\n")
+    }
+}
+
+impl ConfigDiff {
+    /// Returns `true` if the configurations are identical.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Retrieve the differences within the specified group, or `None` if the
+    /// group is unchanged.
+    pub fn group(&self, name: &str) -> Option<&GroupDiff> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// List the group/variable pairs that differ.
+    pub fn keys(&self) -> Vec<(&str, &str)> {
+        self.groups.iter()
+            .flat_map(|g| g.changes.iter().map(move |c| {
+                (g.name.as_str(), c.variable())
+            }))
+            .collect()
+    }
+
+    /// Render the differences as a JSON object.
+    ///
+    /// Each group is a member of the object, containing `added`, `removed`,
+    /// and `changed` objects keyed by variable name. Added and removed
+    /// variables map to their values; changed variables map to an object with
+    /// `old` and `new` members.
+    pub fn to_json(&self) -> String {
+        use fmt::Write as _;
+
+        let mut json = String::from("{");
+
+        for (i, group) in self.groups.iter().enumerate() {
+            if i > 0 { json.push(','); }
+
+            let mut added = vec![];
+            let mut removed = vec![];
+            let mut changed = vec![];
+
+            for change in &group.changes {
+                match change {
+                    Change::Added { variable, value } => added.push(
+                        format!("{}:{}", json_str(variable), json_str(value))
+                    ),
+                    Change::Removed { variable, value } => removed.push(
+                        format!("{}:{}", json_str(variable), json_str(value))
+                    ),
+                    Change::Changed { variable, old, new } => changed.push(
+                        format!("{}:{{\"old\":{},\"new\":{}}}",
+                            json_str(variable), json_str(old), json_str(new))
+                    ),
+                }
+            }
+
+            write!(json,
+                "{}:{{\"added\":{{{}}},\"removed\":{{{}}},\"changed\":{{{}}}}}",
+                json_str(&group.name),
+                added.join(","),
+                removed.join(","),
+                changed.join(","),
+            ).expect("Formatting to a String cannot fail");
+        }
+
+        json.push('}');
+        json
+    }
+}
+
+/// Format the differences as text.
+///
+/// Each group with differences is introduced by its header, followed by a
+/// line per variable: `+ var = value` for additions, `- var = value` for
+/// removals, and `~ var = old -> new` for changes. Values are quoted as when
+/// writing a [Config], except that multi-line values are always quoted so
+/// that each change fits on a single line.
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = ParserOptions::default();
+        let q = |val: &str| if val.contains('\n') {
+            quote_always(val)
+        } else {
+            quote(val, &options).into_owned()
+        };
+
+        for group in &self.groups {
+            writeln!(f, "[{}]", group.name)?;
+
+            for change in &group.changes {
+                match change {
+                    Change::Added { variable, value } =>
+                        writeln!(f, "+ {} = {}", variable, q(value))?,
+                    Change::Removed { variable, value } =>
+                        writeln!(f, "- {} = {}", variable, q(value))?,
+                    Change::Changed { variable, old, new } => writeln!(
+                        f, "~ {} = {} -> {}", variable, q(old), q(new)
+                    )?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Format `s` as a JSON string.
+fn json_str(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 =>
+                json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn diff_configs() {
+        let old: Config = "\
+a = 1
+[Same]
+x = 1
+[Changed]
+x = 1
+y = 2
+z = 3
+[Removed]
+x = 1
+".parse().unwrap();
+
+        let new: Config = "\
+a = 1
+[Added]
+x = 1
+[Changed]
+w = 0
+z = 3
+y = 4
+[Same]
+x = 1
+".parse().unwrap();
+
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.groups.len(), 3);
+        assert_eq!(diff.group("Changed").unwrap().changes, vec![
+            Change::Removed { variable: "x".into(), value: "1".into() },
+            Change::Changed {
+                variable: "y".into(),
+                old: "2".into(),
+                new: "4".into(),
+            },
+            Change::Added { variable: "w".into(), value: "0".into() },
+        ]);
+        assert_eq!(diff.keys(), vec![
+            ("Changed", "x"),
+            ("Changed", "y"),
+            ("Changed", "w"),
+            ("Removed", "x"),
+            ("Added", "x"),
+        ]);
+        assert!(diff.group("Same").is_none());
+
+        assert!(old.diff(&old).is_empty());
+        assert_eq!(old.diff(&old).to_string(), "");
+        assert_eq!(old.diff(&old).to_json(), "{}");
+    }
+
+    #[test]
+    fn repeated_values_differ() {
+        let old: Config = "a = 1\na = 2\n".parse().unwrap();
+        let new: Config = "a = 2\n".parse().unwrap();
+
+        assert_eq!(
+            old.diff(&new).to_string(),
+            "[DEFAULT]\n~ a = \"1\\n2\" -> 2\n"
+        );
+    }
+
+    #[test]
+    fn render_special_values() {
+        let old = Config::default().set("G \"1\"", "v", "a\nb");
+        let new = Config::default().set("G \"1\"", "v", " \t\u{1} ");
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.to_string(),
+            "[G \"1\"]\n~ v = \"a\\nb\" -> \" \\t\\u{1} \"\n"
+        );
+        assert_eq!(
+            diff.to_json(),
+            concat!(
+                r#"{"G \"1\"":{"added":{},"removed":{},"#,
+                r#""changed":{"v":{"old":"a\nb","new":" \t\u0001 "}}}}"#,
+            )
+        );
+
+        let literal = Config::default().set("G", "v", "a\\nb");
+        let multi_line = Config::default().set("G", "v", "a\nb");
+        assert_eq!(
+            literal.diff(&multi_line).to_string(),
+            "[G]\n~ v = a\\nb -> \"a\\nb\"\n"
+        );
+    }
+}
@@ -0,0 +1,945 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Comment-preserving INI documents
+//!
+//! A [Config] only stores values; writing one to a file loses any comments,
+//! blank lines, and the original layout. A [Document] instead keeps every line
+//! of the file so that values can be edited in place; lines that are not
+//! edited are written back byte-for-byte.
+//!
+//! # Example
+//!
+//! ```
+//! # use utility_belt::config::Document;
+//! let text = "; Server settings\n[Server]\nport = 80 \n";
+//! let mut doc: Document = text.parse().unwrap();
+//!
+//! doc.set("Server", "port", "8080");
+//! doc.set("Server", "host", "localhost");
+//!
+//! assert_eq!(
+//!     doc.to_string(),
+//!     "; Server settings\n[Server]\nport = 8080\nhost = localhost\n"
+//! );
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use super::{
+    Config,
+    Duplicates,
+    FileError,
+    ParserOptions,
+    WriteOptions,
+    parser::{
+        classify,
+        continuation,
+        continued_comment,
+        indent,
+        inline_comment,
+        parse_value,
+        Line as LineKind,
+    },
+    quote::quote,
+    write::write_file,
+};
+
+
+/// An editable INI document that preserves comments and formatting.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    source: PathBuf,
+    options: ParserOptions,
+    lines: Vec<Line>,
+}
+
+/// A single line of a [Document].
+#[derive(Debug, Clone)]
+struct Line {
+    /// The text of the line, without its line ending.
+    raw: String,
+    /// The line ending: "\n", "\r\n", or "" for a final unterminated line.
+    ending: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Blank,
+    Comment,
+    Group(String),
+    /// A variable assignment; `value_start` is the byte offset of the value
+    /// within the line, and `value` is the complete value, including any
+    /// continuation lines.
+    Entry { var: String, value_start: usize, value: String },
+    /// A line continuing the value of the preceding entry, or a comment within
+    /// the value.
+    Continuation,
+}
+
+impl Document {
+    /// Read a [Document] from the INI file at the path specified.
+    ///
+    /// # Returns
+    ///
+    /// Returns the document if successfully read; otherwise returns a list of
+    /// errors that occurred while reading or parsing the file.
+    pub fn read_from_file_blocking(path: &Path) -> Result<Self, Vec<FileError>>
+    {
+        Self::read_from_file_blocking_with(path, &ParserOptions::default())
+    }
+
+    /// Read a [Document] from the INI file at the path specified, using the
+    /// dialect described by `options`.
+    ///
+    /// See [Document::read_from_file_blocking] for more information.
+    pub fn read_from_file_blocking_with(path: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse_with(&text, path, options),
+            Err(e) => Err(vec![FileError::IO((path.into(), e.kind()))]),
+        }
+    }
+
+    /// Write this document to the given file. If the file exists, it is
+    /// replaced atomically with the contents of this document, keeping its
+    /// permissions; see [Config::write_to_file_blocking_with].
+    pub fn write_to_file_blocking(&self, path: &Path) -> Result<(), FileError> {
+        self.write_to_file_blocking_with(path, &WriteOptions::default())
+    }
+
+    /// Write this document to the given file with the specified
+    /// [WriteOptions].
+    ///
+    /// A document is always written in its original order, so
+    /// [WriteOptions::sorted] has no effect. See
+    /// [Document::write_to_file_blocking] for more information.
+    pub fn write_to_file_blocking_with(
+        &self,
+        path: &Path,
+        options: &WriteOptions,
+    ) -> Result<(), FileError> {
+        write_file(path, &self.to_string(), options.backup)
+    }
+
+    /// Parse a [Document] from INI text.
+    ///
+    /// `source_name` names the source of the data (typically a file path); it
+    /// is used to describe the location of errors.
+    pub fn parse(text: &str, source_name: impl AsRef<Path>)
+    -> Result<Self, Vec<FileError>> {
+        Self::parse_with(text, source_name, &ParserOptions::default())
+    }
+
+    /// Parse a [Document] from INI text, using the dialect described by
+    /// `options`.
+    ///
+    /// The options are also used to find, add, and write variables. As when
+    /// parsing a [Config], a variable set more than once within a group is an
+    /// error if the options specify [Duplicates::Error].
+    pub fn parse_with(
+        text: &str,
+        source_name: impl AsRef<Path>,
+        options: &ParserOptions,
+    ) -> Result<Self, Vec<FileError>> {
+        let source = source_name.as_ref().to_owned();
+        let mut lines: Vec<Line> = vec![];
+        let mut errors = vec![];
+        // The index and indentation of the entry that continuation lines
+        // would extend.
+        let mut open: Option<(usize, usize)> = None;
+        let mut group = "DEFAULT".to_string();
+        let mut seen = HashSet::new();
+
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let (raw, ending) = split_ending(line);
+
+            if let Some((entry, entry_indent)) = open {
+                if continued_comment(raw, entry_indent, options) {
+                    lines.push(Line {
+                        raw: raw.into(),
+                        ending: ending.into(),
+                        kind: Kind::Continuation,
+                    });
+                    continue;
+                }
+
+                if let Some(text) = continuation(raw, entry_indent, options) {
+                    if let Kind::Entry { ref mut value, .. } =
+                        lines[entry].kind
+                    {
+                        if ! value.is_empty() { value.push('\n'); }
+                        value.push_str(text);
+                    }
+
+                    lines.push(Line {
+                        raw: raw.into(),
+                        ending: ending.into(),
+                        kind: Kind::Continuation,
+                    });
+                    continue;
+                }
+            }
+
+            open = None;
+
+            let kind = match classify(raw, options) {
+                LineKind::Blank => Ok(Kind::Blank),
+                LineKind::Comment => Ok(Kind::Comment),
+                LineKind::Group(name) => {
+                    group = options.group_name(options.group_header(name).0);
+                    Ok(Kind::Group(name.into()))
+                },
+                LineKind::Assignment(var, val) => {
+                    let key = (group.clone(), options.fold(var));
+
+                    if options.duplicates == Duplicates::Error
+                        && ! seen.insert(key)
+                    {
+                        errors.push(FileError::Parse {
+                            file: source.clone(),
+                            msg: "Duplicate variable".into(),
+                            data: raw.trim().into(),
+                            line: i as u32 + 1,
+                        });
+                    }
+
+                    parse_value(val, options).map(|(value, quoted)| {
+                        // Quoted values cannot be continued.
+                        if ! quoted {
+                            open = Some((lines.len(), indent(raw)));
+                        }
+
+                        Kind::Entry {
+                            var: var.into(),
+                            value_start: value_start(raw, options),
+                            value,
+                        }
+                    })
+                },
+                LineKind::Invalid(msg) => Err(msg),
+            };
+
+            let kind = match kind {
+                Ok(kind) => kind,
+                Err(msg) => {
+                    errors.push(FileError::Parse {
+                        file: source.clone(),
+                        msg: msg.into(),
+                        data: raw.trim().into(),
+                        line: i as u32 + 1,
+                    });
+                    continue;
+                },
+            };
+
+            lines.push(Line { raw: raw.into(), ending: ending.into(), kind });
+        }
+
+        if errors.is_empty() {
+            Ok(Self { source, options: options.clone(), lines })
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Convert this document to a [Config].
+    pub fn to_config(&self) -> Result<Config, Vec<FileError>> {
+        let text = self.to_string();
+        Config::from_reader_with(text.as_bytes(), &self.source, &self.options)
+    }
+
+    /// Get the list of groups in the document, in the order they first
+    /// appear.
+    ///
+    /// The DEFAULT group is only listed if it contains variables or is
+    /// explicitly named.
+    pub fn groups(&self) -> Vec<&str> {
+        let mut groups: Vec<&str> = vec![];
+
+        for (group, line) in self.grouped_lines() {
+            let named = matches!(line.kind, Kind::Group(_));
+            let entry = matches!(line.kind, Kind::Entry { .. });
+
+            if (named || entry) && ! groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+
+        groups
+    }
+
+    /// Retrieve the value of the specified variable within the specified group,
+    /// or `None` if it is not set.
+    pub fn get(&self, group: &str, variable: &str) -> Option<&str> {
+        self.find(group, variable)
+            .map(|i| match self.lines[i].kind {
+                Kind::Entry { ref value, .. } => value.as_str(),
+                _ => unreachable!(),
+            })
+    }
+
+    /// Set the value of the specified variable.
+    ///
+    /// If the variable is already set, its value is replaced in place;
+    /// otherwise it is added after the last variable of its group, creating
+    /// the group at the end of the document if necessary.
+    ///
+    /// If the value contains newlines, each subsequent line of the value is
+    /// written as an indented continuation line. Values that would not
+    /// otherwise be read back unchanged are quoted.
+    pub fn set(&mut self, group: &str, variable: &str, value: &str) {
+        let quoted = quote(value, &self.options);
+        let mut value_lines = quoted.split('\n');
+        let first = value_lines.next().unwrap_or_default();
+
+        if let Some(i) = self.find(group, variable) {
+            let raw = &self.lines[i].raw;
+            let prefix = format!("{}    ", &raw[..indent(raw)]);
+            let continued: Vec<_> = value_lines
+                .map(|l| self.new_line(prefix.clone() + l, Kind::Continuation))
+                .collect();
+
+            let options = &self.options;
+            let line = &mut self.lines[i];
+
+            if let Kind::Entry { ref mut value_start, value: ref mut val, .. }
+                = line.kind
+            {
+                // Keep any inline comment, along with its spacing.
+                let comment = inline_comment(&line.raw[*value_start..], options)
+                    .to_string();
+                line.raw.truncate(*value_start);
+
+                // Keep "var = " spacing when replacing an empty "var =".
+                if let Some(sep) = line.raw.chars().last()
+                    .filter(|c| options.separators.contains(c))
+                {
+                    let raw = line.raw.trim_end_matches(sep);
+                    if raw.ends_with(char::is_whitespace) {
+                        line.raw.push(' ');
+                    }
+                }
+
+                *value_start = line.raw.len();
+                // An empty value followed by a comment must be quoted, or
+                // the comment would be read as the value.
+                if first.is_empty() && ! comment.is_empty() {
+                    line.raw.push_str("\"\"");
+                } else {
+                    line.raw.push_str(first);
+                }
+                line.raw.push_str(&comment);
+                *val = value.into();
+            }
+
+            let end = self.entry_end(i);
+            self.lines.drain(i+1..end);
+
+            for (n, line) in continued.into_iter().enumerate() {
+                self.insert(i + 1 + n, line);
+            }
+            return;
+        }
+
+        let sep = self.options.separator();
+        let mut entry = vec![self.new_line(
+            format!("{} {} {}", variable, sep, first),
+            Kind::Entry {
+                var: variable.into(),
+                value_start: variable.len() + sep.len_utf8() + 2,
+                value: value.into(),
+            }
+        )];
+        entry.extend(value_lines.map(|l| {
+            self.new_line(format!("    {}", l), Kind::Continuation)
+        }));
+
+        match self.insertion_point(group) {
+            Some(i) => {
+                for (n, line) in entry.into_iter().enumerate() {
+                    self.insert(i + n, line);
+                }
+            },
+            None => {
+                if self.lines.iter().any(|l| l.kind != Kind::Blank) {
+                    let blank = self.new_line(String::new(), Kind::Blank);
+                    self.insert(self.lines.len(), blank);
+                }
+
+                let header = self.new_line(
+                    format!("[{}]", group),
+                    Kind::Group(group.into())
+                );
+                self.insert(self.lines.len(), header);
+
+                for line in entry {
+                    self.insert(self.lines.len(), line);
+                }
+            },
+        }
+    }
+
+    /// Remove the specified variable from the document, returning its value.
+    ///
+    /// If the variable is set multiple times, every assignment is removed.
+    pub fn remove(&mut self, group: &str, variable: &str) -> Option<String> {
+        let value = self.get(group, variable).map(String::from);
+        let mut removing = false;
+
+        let remove: Vec<bool> = self.grouped_lines()
+            .map(|(g, line)| {
+                removing = match line.kind {
+                    Kind::Entry { ref var, .. } => self.matches(
+                        (g, var), (group, variable)
+                    ),
+                    Kind::Continuation => removing,
+                    _ => false,
+                };
+                removing
+            })
+            .collect();
+
+        self.retain_lines(remove);
+        value
+    }
+
+    /// Remove the specified group, including its header and every line
+    /// (including comments) within it.
+    ///
+    /// Returns `true` if the group was present.
+    pub fn remove_group(&mut self, group: &str) -> bool {
+        let remove: Vec<bool> = self.grouped_lines()
+            .map(|(g, line)| {
+                self.same_group(g, group) && (
+                    g != "DEFAULT"
+                    || matches!(
+                        line.kind,
+                        Kind::Group(_) | Kind::Entry { .. } | Kind::Continuation
+                    )
+                )
+            })
+            .collect();
+
+        let found = remove.iter().any(|r| *r);
+        self.retain_lines(remove);
+        found
+    }
+
+    /// Iterate over the lines of the document, paired with the name of the
+    /// group each belongs to.
+    ///
+    /// The group named by [ParserOptions::default_group] is reported as
+    /// DEFAULT, and any parent named in a group header is omitted.
+    fn grouped_lines(&self) -> impl Iterator<Item = (&str, &Line)> {
+        let mut group = "DEFAULT";
+
+        self.lines.iter().map(move |line| {
+            if let Kind::Group(ref header) = line.kind {
+                let (name, _) = self.options.group_header(header);
+
+                group = if self.options.group_name(name) == "DEFAULT" {
+                    "DEFAULT"
+                } else {
+                    name
+                };
+            }
+            (group, line)
+        })
+    }
+
+    /// Determine whether two names refer to the same group, treating the
+    /// group named by [ParserOptions::default_group] as DEFAULT.
+    fn same_group(&self, a: &str, b: &str) -> bool {
+        self.options.group_name(a) == self.options.group_name(b)
+    }
+
+    /// Determine whether two group/variable pairs name the same variable.
+    fn matches(&self, a: (&str, &str), b: (&str, &str)) -> bool {
+        self.same_group(a.0, b.0) && self.options.names_match(a.1, b.1)
+    }
+
+    /// Find the index of the line that sets the variable's value.
+    ///
+    /// If a variable is set multiple times, the last assignment wins unless
+    /// the options keep the first.
+    fn find(&self, group: &str, variable: &str) -> Option<usize> {
+        let mut found = self.grouped_lines()
+            .enumerate()
+            .filter(|(_, (g, line))| matches!(
+                line.kind,
+                Kind::Entry { ref var, .. }
+                    if self.matches((g, var), (group, variable))
+            ))
+            .map(|(i, _)| i);
+
+        if self.options.duplicates == Duplicates::First {
+            found.next()
+        } else {
+            found.last()
+        }
+    }
+
+    /// Determine where a new variable in `group` should be inserted, or `None`
+    /// if the group does not exist.
+    fn insertion_point(&self, group: &str) -> Option<usize> {
+        let mut point = None;
+
+        for (i, (g, line)) in self.grouped_lines().enumerate() {
+            if ! self.same_group(g, group) { continue; }
+
+            match line.kind {
+                Kind::Group(_) | Kind::Entry { .. } | Kind::Continuation =>
+                    point = Some(i + 1),
+                _ => {},
+            }
+        }
+
+        if point.is_none() && self.same_group(group, "DEFAULT") {
+            // Place new default variables above the first group, keeping any
+            // blank lines that separate it from the rest of the file.
+            let mut i = self.lines.iter()
+                .position(|l| matches!(l.kind, Kind::Group(_)))
+                .unwrap_or(self.lines.len());
+
+            while i > 0 && self.lines[i-1].kind == Kind::Blank { i -= 1; }
+            point = Some(i);
+        }
+
+        point
+    }
+
+    /// Find the index following the last continuation line of the entry at
+    /// `index`.
+    fn entry_end(&self, index: usize) -> usize {
+        index + 1 + self.lines[index+1..].iter()
+            .take_while(|l| l.kind == Kind::Continuation)
+            .count()
+    }
+
+    /// Create a line using the document's predominant line ending.
+    fn new_line(&self, raw: String, kind: Kind) -> Line {
+        let ending = self.lines.iter()
+            .map(|l| l.ending.as_str())
+            .find(|e| ! e.is_empty())
+            .unwrap_or("\n");
+
+        Line { raw, ending: ending.into(), kind }
+    }
+
+    /// Insert a line, terminating the previous line if it was the final
+    /// unterminated line of the document.
+    fn insert(&mut self, index: usize, line: Line) {
+        if index == self.lines.len() {
+            if let Some(last) = self.lines.last_mut() {
+                if last.ending.is_empty() {
+                    last.ending = line.ending.clone();
+                }
+            }
+        }
+        self.lines.insert(index, line);
+    }
+
+    /// Remove every line whose corresponding entry in `remove` is `true`.
+    fn retain_lines(&mut self, remove: Vec<bool>) {
+        let mut remove = remove.into_iter();
+        self.lines.retain(|_| ! remove.next().unwrap_or(false));
+    }
+}
+
+/// Split a line into its text and line ending.
+fn split_ending(line: &str) -> (&str, &str) {
+    if let Some(raw) = line.strip_suffix("\r\n") {
+        (raw, "\r\n")
+    } else if let Some(raw) = line.strip_suffix('\n') {
+        (raw, "\n")
+    } else {
+        (line, "")
+    }
+}
+
+/// Find the byte offset of the value in an assignment line.
+fn value_start(raw: &str, options: &ParserOptions) -> usize {
+    let sep = raw.find(options.separators.as_slice())
+        .expect("Assignment must contain a separator");
+    let start = sep + raw[sep..].chars().next().map_or(1, char::len_utf8);
+    start + indent(&raw[start..])
+}
+
+/// Parse a [Document] from INI text.
+///
+/// Errors are reported as originating in a file named `<string>`; use
+/// [Document::parse] to provide a different name.
+impl FromStr for Document {
+    type Err = Vec<FileError>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, "<string>")
+    }
+}
+
+/// Format the document as INI text.
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}{}", line.raw, line.ending)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    const TEXT: &str = "\
+; Global settings
+var1 = val1
+
+; The first group
+[Group A]
+  var2   =   value two ; not a comment
+  var 3=value = three
+
+[Group B]\r
+; Nothing here
+";
+
+    #[test]
+    fn round_trip_is_identical() {
+        let doc: Document = TEXT.parse().unwrap();
+        assert_eq!(doc.to_string(), TEXT);
+
+        let doc: Document = "[A]\r\nb = c".parse().unwrap();
+        assert_eq!(doc.to_string(), "[A]\r\nb = c");
+    }
+
+    #[test]
+    fn read_from_file_round_trips() {
+        let path = Path::new("tests/test.ini");
+        let doc = Document::read_from_file_blocking(path).unwrap();
+
+        assert_eq!(doc.to_string(), std::fs::read_to_string(path).unwrap());
+        assert_eq!(doc.get("Group A", "var 3"), Some("value = three"));
+    }
+
+    #[test]
+    fn write_with_backup() {
+        let path = std::env::temp_dir()
+            .join(format!("ub_document_{}.ini", std::process::id()));
+        let backup = path.with_extension("ini.bak");
+        std::fs::write(&path, "; old\na = 1\n").unwrap();
+
+        let mut doc = Document::read_from_file_blocking(&path).unwrap();
+        doc.set("DEFAULT", "a", "2");
+        let options = WriteOptions::new().backup(true);
+        doc.write_to_file_blocking_with(&path, &options).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "; old\na = 2\n");
+        assert_eq!(
+            std::fs::read_to_string(&backup).unwrap(),
+            "; old\na = 1\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn parse_errors_are_collected() {
+        let errs = Document::read_from_file_blocking(
+            Path::new("tests/invalid.ini")
+        ).unwrap_err();
+
+        assert_eq!(errs.len(), 4);
+        match &errs[1] {
+            FileError::Parse { data, line, .. } => {
+                assert_eq!(data, "= some value");
+                assert_eq!(*line, 5);
+            },
+            _ => panic!("Expected a FileError::Parse"),
+        }
+    }
+
+    #[test]
+    fn get_values() {
+        let doc: Document = TEXT.parse().unwrap();
+
+        assert_eq!(doc.get("DEFAULT", "var1"), Some("val1"));
+        assert_eq!(
+            doc.get("Group A", "var2"),
+            Some("value two ; not a comment")
+        );
+        assert_eq!(doc.get("Group A", "var 3"), Some("value = three"));
+        assert_eq!(doc.get("Group B", "var1"), None);
+        assert_eq!(doc.groups(), vec!["DEFAULT", "Group A", "Group B"]);
+    }
+
+    #[test]
+    fn set_existing_value_in_place() {
+        let mut doc: Document = TEXT.parse().unwrap();
+        doc.set("Group A", "var2", "new");
+        doc.set("Group A", "var 3", "3");
+
+        assert_eq!(
+            doc.to_string(),
+            TEXT.replace("value two ; not a comment", "new")
+                .replace("value = three", "3")
+        );
+    }
+
+    #[test]
+    fn set_new_values() {
+        let mut doc: Document = TEXT.parse().unwrap();
+        doc.set("Group A", "var4", "four");
+        doc.set("Group B", "var5", "five");
+        doc.set("DEFAULT", "var6", "six");
+        doc.set("Group C", "var7", "seven");
+
+        assert_eq!(doc.to_string(), "\
+; Global settings
+var1 = val1
+var6 = six
+
+; The first group
+[Group A]
+  var2   =   value two ; not a comment
+  var 3=value = three
+var4 = four
+
+[Group B]\r
+var5 = five
+; Nothing here
+
+[Group C]
+var7 = seven
+");
+    }
+
+    #[test]
+    fn set_after_unterminated_line() {
+        let mut doc: Document = "[A]\r\nb =".parse().unwrap();
+        doc.set("A", "b", "c");
+        assert_eq!(doc.get("A", "b"), Some("c"));
+        doc.set("A", "d", "e");
+        assert_eq!(doc.to_string(), "[A]\r\nb = c\r\nd = e\r\n");
+
+        let mut doc = Document::default();
+        doc.set("DEFAULT", "a", "b");
+        assert_eq!(doc.to_string(), "a = b\n");
+    }
+
+    #[test]
+    fn remove_values_and_groups() {
+        let mut doc: Document = TEXT.parse().unwrap();
+
+        assert_eq!(
+            doc.remove("Group A", "var 3"),
+            Some("value = three".into())
+        );
+        assert_eq!(doc.remove("Group A", "var 3"), None);
+        assert!(doc.remove_group("Group B"));
+        assert!(! doc.remove_group("Group B"));
+
+        assert_eq!(doc.to_string(), "\
+; Global settings
+var1 = val1
+
+; The first group
+[Group A]
+  var2   =   value two ; not a comment
+
+");
+
+        assert!(doc.remove_group("DEFAULT"));
+        assert!(doc.to_string().starts_with("; Global settings\n\n"));
+    }
+
+    #[test]
+    fn multi_line_values() {
+        let text = "\
+[A]
+  lines = one
+      two
+  ; comment
+  next = 1
+";
+        let mut doc: Document = text.parse().unwrap();
+        assert_eq!(doc.to_string(), text);
+        assert_eq!(doc.get("A", "lines"), Some("one\ntwo"));
+
+        doc.set("A", "lines", "1\n2\n3");
+        doc.set("A", "next", "a\nb");
+        assert_eq!(doc.get("A", "lines"), Some("1\n2\n3"));
+        assert_eq!(doc.to_string(), "\
+[A]
+  lines = 1
+      2
+      3
+  ; comment
+  next = a
+      b
+");
+        doc.set("A", "new", "x\ny");
+        doc.set("A", "lines", "single");
+        assert_eq!(doc.to_string(), "\
+[A]
+  lines = single
+  ; comment
+  next = a
+      b
+new = x
+    y
+");
+        assert_eq!(doc.to_config().unwrap()[("A", "next")], "a\nb");
+
+        assert_eq!(doc.remove("A", "next"), Some("a\nb".into()));
+        assert_eq!(doc.to_string(), "[A]\n  lines = single\n  ; comment\n\
+            new = x\n    y\n");
+
+        let text = "a = 1\n    ; note\n    2\nb = 3\n";
+        let mut doc: Document = text.parse().unwrap();
+        assert_eq!(doc.to_string(), text);
+        assert_eq!(doc.get("DEFAULT", "a"), Some("1\n2"));
+        doc.set("DEFAULT", "a", "4");
+        assert_eq!(doc.to_string(), "a = 4\nb = 3\n");
+    }
+
+    #[test]
+    fn quoted_values() {
+        let mut doc: Document = "a = \" x \" \nb = 1\n".parse().unwrap();
+        assert_eq!(doc.get("DEFAULT", "a"), Some(" x "));
+
+        doc.set("DEFAULT", "b", "\tindented\n\nlines");
+        doc.set("DEFAULT", "a", "plain");
+        assert_eq!(
+            doc.to_string(),
+            "a = plain\nb = \"\\tindented\\n\\nlines\"\n"
+        );
+        assert_eq!(doc.get("DEFAULT", "b"), Some("\tindented\n\nlines"));
+        assert_eq!(
+            doc.to_config().unwrap()["b"],
+            "\tindented\n\nlines"
+        );
+
+        assert!("a = \"x".parse::<Document>().is_err());
+    }
+
+    #[test]
+    fn custom_dialect() {
+        let options = ParserOptions::new()
+            .comment_prefixes(&["#"])
+            .separators(&[':'])
+            .case_sensitive(false)
+            .default_group("general")
+            .duplicates(Duplicates::First);
+
+        let text = "# comment\n[General]\nName: app\nname: other\n[Server]\n";
+        let mut doc = Document::parse_with(text, "<string>", &options)
+            .unwrap();
+
+        assert_eq!(doc.groups(), vec!["DEFAULT", "Server"]);
+        assert_eq!(doc.get("DEFAULT", "NAME"), Some("app"));
+
+        doc.set("default", "name", "# new");
+        doc.set("SERVER", "port", "80");
+        assert_eq!(
+            doc.to_string(),
+            "# comment\n[General]\nName: \"# new\"\nname: other\n[Server]\n\
+                port : 80\n"
+        );
+
+        let conf = doc.to_config().unwrap();
+        assert_eq!(conf["name"], "# new");
+        assert_eq!(conf[("server", "port")], "80");
+    }
+
+    #[test]
+    fn custom_default_group() {
+        let options = ParserOptions::new().default_group("general");
+
+        let mut doc = Document::parse_with("[A]\nx = 1\n", "<string>", &options)
+            .unwrap();
+        doc.set("general", "x", "2");
+        doc.set("general", "y", "3");
+        doc.set("DEFAULT", "z", "4");
+
+        assert_eq!(doc.get("DEFAULT", "y"), Some("3"));
+        assert_eq!(doc.to_string(), "x = 2\ny = 3\nz = 4\n[A]\nx = 1\n");
+
+        assert!(doc.remove_group("general"));
+        assert_eq!(doc.to_string(), "[A]\nx = 1\n");
+    }
+
+    #[test]
+    fn duplicate_variables_are_errors() {
+        let options = ParserOptions::new().duplicates(Duplicates::Error);
+        let text = "x = 1\n[A]\nx = 2\n    more\ny = 3\nx = 4\n[a]\nx = 5\n";
+
+        let errs = Document::parse_with(text, "<string>", &options)
+            .unwrap_err();
+
+        assert_eq!(errs.len(), 1);
+        match &errs[0] {
+            FileError::Parse { msg, data, line, .. } => {
+                assert_eq!(msg, "Duplicate variable");
+                assert_eq!(data, "x = 4");
+                assert_eq!(*line, 6);
+            },
+            _ => panic!("Expected a FileError::Parse"),
+        }
+
+        assert!(Document::parse(text, "<string>").is_ok());
+    }
+
+    #[test]
+    fn keep_inline_comments() {
+        let options = ParserOptions::new().inline_comment_prefixes(&["#"]);
+        let text = "\
+port = 80   # default
+host = \"a # b\" # quoted
+debug = true
+";
+        let mut doc = Document::parse_with(text, "<string>", &options)
+            .unwrap();
+        assert_eq!(doc.get("DEFAULT", "port"), Some("80"));
+
+        doc.set("DEFAULT", "port", "8080");
+        doc.set("DEFAULT", "host", "c");
+        doc.set("DEFAULT", "debug", "false");
+        assert_eq!(
+            doc.to_string(),
+            "port = 8080   # default\nhost = c # quoted\ndebug = false\n"
+        );
+
+        doc.set("DEFAULT", "port", "");
+        doc.set("DEFAULT", "host", "x\ny");
+        assert_eq!(
+            doc.to_string(),
+            "port = \"\"   # default\nhost = x # quoted\n    y\n\
+                debug = false\n"
+        );
+
+        let conf = doc.to_config().unwrap();
+        assert_eq!(conf["port"], "");
+        assert_eq!(conf["host"], "x\ny");
+    }
+
+    #[test]
+    fn convert_to_config() {
+        let mut doc: Document = TEXT.parse().unwrap();
+        doc.set("Group B", "var5", "five");
+
+        let conf = doc.to_config().unwrap();
+        assert_eq!(conf[("Group A", "var 3")], "value = three");
+        assert_eq!(conf[("Group B", "var5")], "five");
+    }
+}
@@ -0,0 +1,290 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! In-place access to a single variable
+//!
+//! [Config::entry] returns an [Entry] for a variable, modelled on
+//! `std::collections::hash_map::Entry`, so that a value can be inspected and
+//! then modified, inserted, or removed without looking it up repeatedly.
+//!
+//! An entry refers to the variable in the named group only; values inherited
+//! from other groups are not considered.
+//!
+//! Mutable access to a value is given through a [ValueMut], which records the
+//! value as [Origin::Set] only when it is written; a value that is merely
+//! looked up keeps its origin and any list of repeated values.
+//!
+//! # Example
+//!
+//! ```
+//! # use utility_belt::config::Config;
+//! let mut conf = Config::default().set("Server", "port", "80");
+//!
+//! conf.entry("Server", "port").and_modify(|port| port.push('8'));
+//! conf.entry("Server", "host").or_insert("localhost");
+//!
+//! assert_eq!(conf[("Server", "port")], "808");
+//! assert_eq!(conf[("Server", "host")], "localhost");
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use super::{Config, Key, Origin, Value};
+
+
+/// A view into a single variable of a [Config], which may be set or unset.
+///
+/// See the [entry module](self) documentation.
+#[derive(Debug)]
+pub enum Entry<'a> {
+    /// The variable is set.
+    Occupied(OccupiedEntry<'a>),
+    /// The variable is not set.
+    Vacant(VacantEntry<'a>),
+}
+
+/// A variable that is set; part of an [Entry].
+#[derive(Debug)]
+pub struct OccupiedEntry<'a> {
+    conf: &'a mut Config,
+    index: usize,
+}
+
+/// A variable that is not set; part of an [Entry].
+#[derive(Debug)]
+pub struct VacantEntry<'a> {
+    conf: &'a mut Config,
+    key: Key,
+}
+
+/// A mutable reference to the value of a variable.
+///
+/// Writing through the reference makes the value's [Origin] [Origin::Set] and
+/// discards any list of repeated values; reading leaves both unchanged.
+#[derive(Debug)]
+pub struct ValueMut<'a> {
+    value: &'a mut Value,
+}
+
+impl Config {
+    /// Get the [Entry] for the specified variable for in-place manipulation.
+    pub fn entry(&mut self, group: &str, variable: &str) -> Entry<'_> {
+        let key = self.key(group, variable);
+
+        match self.values.index.get(&key) {
+            Some(&index) =>
+                Entry::Occupied(OccupiedEntry { conf: self, index }),
+            None => Entry::Vacant(VacantEntry { conf: self, key }),
+        }
+    }
+}
+
+impl<'a> Entry<'a> {
+    /// The group and variable names of this entry.
+    pub fn key(&self) -> (&str, &str) {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Set the variable to `default` if it is not set, and return a mutable
+    /// reference to its value.
+    pub fn or_insert(self, default: &str) -> ValueMut<'a> {
+        self.or_insert_with(|| default.into())
+    }
+
+    /// Set the variable to the result of `default` if it is not set, and
+    /// return a mutable reference to its value.
+    pub fn or_insert_with<F>(self, default: F) -> ValueMut<'a>
+        where F: FnOnce() -> String,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(&default()),
+        }
+    }
+
+    /// Modify the value if the variable is set.
+    pub fn and_modify<F>(self, f: F) -> Self
+        where F: FnOnce(&mut String),
+    {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(&mut e.get_mut());
+                Entry::Occupied(e)
+            },
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    /// The group and variable names of this entry.
+    pub fn key(&self) -> (&str, &str) {
+        let (group, var) = &self.conf.values.entries[self.index].0;
+        (group, var)
+    }
+
+    /// The variable's value.
+    pub fn get(&self) -> &str {
+        &self.value().val
+    }
+
+    /// Where the variable's value came from.
+    pub fn origin(&self) -> &Origin {
+        &self.value().origin
+    }
+
+    /// A mutable reference to the variable's value.
+    ///
+    /// See [ValueMut].
+    pub fn get_mut(&mut self) -> ValueMut<'_> {
+        ValueMut { value: &mut self.conf.values.entries[self.index].1 }
+    }
+
+    /// Convert the entry into a mutable reference to the variable's value
+    /// that lives as long as the [Config].
+    ///
+    /// See [ValueMut].
+    pub fn into_mut(self) -> ValueMut<'a> {
+        ValueMut { value: &mut self.conf.values.entries[self.index].1 }
+    }
+
+    /// Replace the variable's value, returning the old value.
+    ///
+    /// The value's [Origin] becomes [Origin::Set], and any list of repeated
+    /// values is discarded.
+    pub fn insert(&mut self, val: &str) -> String {
+        std::mem::replace(&mut self.get_mut(), val.into())
+    }
+
+    /// Remove the variable, returning its value.
+    pub fn remove(self) -> String {
+        let key = self.conf.values.entries[self.index].0.clone();
+
+        self.conf.values.remove(&key)
+            .expect("Occupied entries must be set")
+            .val
+    }
+
+    fn value(&self) -> &Value {
+        &self.conf.values.entries[self.index].1
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    /// The group and variable names of this entry.
+    pub fn key(&self) -> (&str, &str) {
+        (&self.key.0, &self.key.1)
+    }
+
+    /// Set the variable, returning a mutable reference to its value.
+    pub fn insert(self, val: &str) -> ValueMut<'a> {
+        let index = self.conf.values.entries.len();
+        self.conf.values.insert(self.key, Value::new(val.into(), Origin::Set));
+
+        ValueMut { value: &mut self.conf.values.entries[index].1 }
+    }
+}
+
+impl Deref for ValueMut<'_> {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.value.val
+    }
+}
+
+/// Record that the value is being modified programmatically.
+impl DerefMut for ValueMut<'_> {
+    fn deref_mut(&mut self) -> &mut String {
+        self.value.origin = Origin::Set;
+        self.value.list.clear();
+        &mut self.value.val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn occupied_entries() {
+        let mut conf: Config = "[A]\nx = 1\nx = 2\ny = 3\n".parse().unwrap();
+
+        match conf.entry("A", "x") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), ("A", "x"));
+                assert_eq!(e.get(), "2");
+                assert!(matches!(e.origin(), Origin::File { line: 3, .. }));
+
+                assert_eq!(e.insert("4"), "2");
+                assert_eq!(e.origin(), &Origin::Set);
+            },
+            Entry::Vacant(_) => panic!("Expected an occupied entry"),
+        }
+
+        assert_eq!(conf.get_list("A", "x").unwrap(), vec!["4"]);
+
+        match conf.entry("A", "x") {
+            Entry::Occupied(e) => assert_eq!(e.remove(), "4"),
+            Entry::Vacant(_) => panic!("Expected an occupied entry"),
+        }
+
+        assert!(conf.get("A", "x").is_none());
+        assert_eq!(conf[("A", "y")], "3");
+        assert_eq!(conf.to_string(), "[A]\ny = 3\n");
+    }
+
+    #[test]
+    fn vacant_entries() {
+        let mut conf = Config::default().set("A", "x", "1");
+
+        let entry = conf.entry("A", "y");
+        assert!(matches!(entry, Entry::Vacant(_)));
+        assert_eq!(entry.key(), ("A", "y"));
+        entry.or_insert("2").push('0');
+
+        *conf.entry("A", "z").or_insert_with(|| "3".into()) += "0";
+        conf.entry("B", "x").and_modify(|_| panic!("Not set")).or_insert("4");
+
+        assert_eq!(conf[("A", "y")], "20");
+        assert_eq!(conf[("A", "z")], "30");
+        assert_eq!(conf[("B", "x")], "4");
+        assert_eq!(
+            conf.to_string(),
+            "[A]\nx = 1\ny = 20\nz = 30\n[B]\nx = 4\n"
+        );
+    }
+
+    #[test]
+    fn reading_keeps_origin() {
+        let mut conf: Config = "[A]\nx = 1\nx = 2\n".parse().unwrap();
+
+        assert_eq!(*conf.entry("A", "x").or_insert("3"), "2");
+        assert_eq!(conf.entry("A", "x").or_insert_with(|| "3".into()).len(), 1);
+
+        assert!(matches!(
+            conf.origin("A", "x"),
+            Some(Origin::File { line: 3, .. })
+        ));
+        assert_eq!(conf.get_list("A", "x").unwrap(), vec!["1", "2"]);
+
+        conf.entry("A", "x").or_insert("3").push('0');
+
+        assert_eq!(conf.origin("A", "x"), Some(&Origin::Set));
+        assert_eq!(conf.get_list("A", "x").unwrap(), vec!["20"]);
+    }
+
+    #[test]
+    fn entries_ignore_inherited_values() {
+        let mut conf = Config::default()
+            .set("base", "x", "1")
+            .set_parent("child", "base");
+
+        assert!(matches!(conf.entry("child", "x"), Entry::Vacant(_)));
+    }
+}
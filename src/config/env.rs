@@ -0,0 +1,277 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Override configuration values with environment variables
+//!
+//! An environment variable named `{PREFIX}_{GROUP}__{VARIABLE}` sets the
+//! variable in the specified group; `{PREFIX}_{VARIABLE}` (with no separator)
+//! sets a variable in the DEFAULT group. For example, with the prefix `MYAPP`:
+//!
+//! | Environment variable  | Group     | Variable |
+//! |-----------------------|-----------|----------|
+//! | `MYAPP_PORT`          | `DEFAULT` | `port`   |
+//! | `MYAPP_DEFAULT__PORT` | `DEFAULT` | `port`   |
+//! | `MYAPP_GROUP_A__VAR2` | `Group A` | `var2`   |
+//!
+//! Since environment variable names are conventionally upper-case and cannot
+//! contain spaces, by default names are matched against the groups and
+//! variables already in the [Config] ignoring case, with an underscore matching
+//! either an underscore or a space. Names that do not match an existing group
+//! or variable are lower-cased, and their underscores replaced by spaces. The
+//! DEFAULT group always matches `DEFAULT`.
+//!
+//! Set default values via [Config::set] prior to merging the environment so
+//! that environment variables map to the intended names. [EnvOptions] can
+//! change the separator and disable the case and space mappings.
+
+use super::{Config, Origin, Value};
+
+
+/// Options that control how environment variable names map to configuration
+/// groups and variables.
+///
+/// See the [module documentation](self) for a description of the mapping.
+#[derive(Debug, Clone)]
+pub struct EnvOptions {
+    separator: String,
+    case_sensitive: bool,
+    underscore_as_space: bool,
+}
+
+impl Default for EnvOptions {
+    fn default() -> Self {
+        Self {
+            separator: "__".into(),
+            case_sensitive: false,
+            underscore_as_space: true,
+        }
+    }
+}
+
+impl EnvOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the separator between the group and variable names. The default
+    /// is a double underscore (`__`).
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// If `true`, names must match existing groups and variables exactly, and
+    /// new names are not lower-cased.
+    pub fn case_sensitive(mut self, sensitive: bool) -> Self {
+        self.case_sensitive = sensitive;
+        self
+    }
+
+    /// If `true` (the default), an underscore in a name matches a space in
+    /// existing names, and is replaced by a space in new names.
+    pub fn underscore_as_space(mut self, enabled: bool) -> Self {
+        self.underscore_as_space = enabled;
+        self
+    }
+
+    /// Determine whether the environment name `env` refers to `name`.
+    fn matches(&self, env: &str, name: &str) -> bool {
+        let normalize = |c: char| {
+            let c = if self.underscore_as_space && c == ' ' { '_' } else { c };
+            if self.case_sensitive { c } else { c.to_ascii_lowercase() }
+        };
+
+        env.chars().map(normalize).eq(name.chars().map(normalize))
+    }
+
+    /// Convert an environment name into a new group or variable name.
+    fn decode(&self, env: &str) -> String {
+        let name = if self.case_sensitive {
+            env.to_string()
+        } else {
+            env.to_lowercase()
+        };
+
+        if self.underscore_as_space {
+            name.replace('_', " ")
+        } else {
+            name
+        }
+    }
+}
+
+impl Config {
+    /// Override configuration values with any environment variables whose
+    /// names start with `{prefix}_`.
+    ///
+    /// Each value set from the environment records the environment variable
+    /// as its [Origin]. See the [env module](super::env) documentation for
+    /// the naming scheme.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::{Config, Origin};
+    /// std::env::set_var("DOC_EXAMPLE_GROUP_A__VAR2", "from env");
+    ///
+    /// let conf = Config::default()
+    ///     .set("Group A", "var2", "default")
+    ///     .merge_env("DOC_EXAMPLE");
+    ///
+    /// assert_eq!(conf[("Group A", "var2")], "from env");
+    /// assert_eq!(
+    ///     conf.origin("Group A", "var2"),
+    ///     Some(&Origin::Env("DOC_EXAMPLE_GROUP_A__VAR2".into()))
+    /// );
+    /// ```
+    pub fn merge_env(self, prefix: &str) -> Self {
+        self.merge_env_with(prefix, &EnvOptions::default())
+    }
+
+    /// Override configuration values with environment variables, using the
+    /// specified [EnvOptions].
+    ///
+    /// See [Config::merge_env] for more information.
+    pub fn merge_env_with(self, prefix: &str, options: &EnvOptions) -> Self {
+        // Variables whose names or values are not valid Unicode cannot be
+        // stored in a Config.
+        let vars = std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?,
+                v.into_string().ok()?)));
+
+        self.merge_vars(prefix, options, vars)
+    }
+
+    /// Merge the provided name/value pairs as if they were environment
+    /// variables.
+    fn merge_vars(
+        mut self,
+        prefix: &str,
+        options: &EnvOptions,
+        vars: impl IntoIterator<Item = (String, String)>
+    ) -> Self {
+        let prefix = format!("{}_", prefix);
+
+        let mut vars: Vec<_> = vars.into_iter()
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .collect();
+
+        // The environment's order is unspecified; sort so that new variables
+        // are added in a predictable order.
+        vars.sort();
+
+        for (name, val) in vars {
+            let rest = &name[prefix.len()..];
+
+            let (group, var) = match rest.split_once(&options.separator) {
+                Some((group, var)) => (group, var),
+                None => ("DEFAULT", rest),
+            };
+
+            if group.is_empty() || var.is_empty() { continue; }
+
+            let group = if group.eq_ignore_ascii_case("DEFAULT") {
+                String::from("DEFAULT")
+            } else {
+                self.groups()
+                    .find(|g| options.matches(group, g))
+                    .cloned()
+                    .unwrap_or_else(|| options.decode(group))
+            };
+
+            let var = self.variables_in_group(&group)
+                .find(|v| options.matches(var, v))
+                .cloned()
+                .unwrap_or_else(|| options.decode(var));
+
+            self.values.insert(
+                self.key(&group, &var),
+                Value::new(val, Origin::Env(name))
+            );
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::ENV_LOCK;
+
+
+    fn vars(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn defaults() -> Config {
+        Config::default()
+            .set_default("port", "80")
+            .set_default("log_level", "warn")
+            .set("Group A", "var 2", "value two")
+    }
+
+    #[test]
+    fn env_overrides_existing_values() {
+        let opts = EnvOptions::default();
+        let conf = defaults().merge_vars("MYAPP", &opts, vars(&[
+            ("MYAPP_DEFAULT__PORT", "8080"),
+            ("MYAPP_LOG_LEVEL", "debug"),
+            ("MYAPP_GROUP_A__VAR_2", "from env"),
+            ("OTHER_PORT", "1"),
+        ]));
+
+        assert_eq!(conf["port"], "8080");
+        assert_eq!(conf["log_level"], "debug");
+        assert_eq!(conf[("Group A", "var 2")], "from env");
+        assert_eq!(
+            conf.origin("DEFAULT", "port"),
+            Some(&Origin::Env("MYAPP_DEFAULT__PORT".into()))
+        );
+        assert_eq!(conf.groups().count(), 2);
+    }
+
+    #[test]
+    fn env_adds_new_values() {
+        let opts = EnvOptions::default();
+        let conf = defaults().merge_vars("MYAPP", &opts, vars(&[
+            ("MYAPP_NEW_GROUP__SOME_VAR", "a"),
+            ("MYAPP_TIMEOUT", "b"),
+        ]));
+
+        assert_eq!(conf[("new group", "some var")], "a");
+        assert_eq!(conf["timeout"], "b");
+    }
+
+    #[test]
+    fn env_options_change_mapping() {
+        let opts = EnvOptions::new()
+            .separator("_")
+            .case_sensitive(true)
+            .underscore_as_space(false);
+
+        let conf = defaults().merge_vars("APP", &opts, vars(&[
+            ("APP_DEFAULT_port", "1"),
+            ("APP_DEFAULT_PORT", "2"),
+            ("APP_Group A_var 2", "3"),
+            ("APP_Extra_log_level", "4"),
+        ]));
+
+        assert_eq!(conf["port"], "1");
+        assert_eq!(conf["PORT"], "2");
+        assert_eq!(conf[("Group A", "var 2")], "3");
+        assert_eq!(conf[("Extra", "log_level")], "4");
+    }
+
+    #[test]
+    fn merge_process_environment() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::set_var("UB_ENV_TEST_GROUP_A__VAR_2", "from env");
+
+        let conf = defaults().merge_env("UB_ENV_TEST");
+        assert_eq!(conf[("Group A", "var 2")], "from env");
+        assert_eq!(conf["port"], "80");
+    }
+}
@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A globally-available, reloadable configuration
+//!
+//! [set_global] installs a [Config] that can be retrieved from anywhere via
+//! [Config::global] or [Config::try_global]. The global configuration can be
+//! replaced at any time; code that has already retrieved it keeps the
+//! configuration it retrieved.
+//!
+//! [override_global] temporarily replaces the global configuration on the
+//! current thread only, so that tests running in the same process can each use
+//! their own configuration.
+
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use super::Config;
+
+
+static GLOBAL: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+thread_local! {
+    static OVERRIDES: RefCell<Vec<Arc<Config>>> = const {
+        RefCell::new(vec![])
+    };
+}
+
+/// Make the provided [Config] available globally, replacing any existing
+/// global configuration.
+///
+/// Use [Config::global] to obtain the global `Config` instance.
+///
+/// # Returns
+///
+/// Returns the previous global configuration, if any.
+///
+/// # Example
+///
+/// ```
+/// # use utility_belt::config::{Config, set_global};
+/// set_global(Config::default().set_default("port", "80"));
+/// assert_eq!(Config::global()["port"], "80");
+///
+/// let old = set_global(Config::default().set_default("port", "8080"));
+/// assert_eq!(old.unwrap()["port"], "80");
+/// assert_eq!(Config::global()["port"], "8080");
+/// ```
+pub fn set_global(conf: impl Into<Arc<Config>>) -> Option<Arc<Config>> {
+    let mut global = GLOBAL.write().unwrap_or_else(PoisonError::into_inner);
+    global.replace(conf.into())
+}
+
+/// Replace the global configuration on the current thread until the returned
+/// guard is dropped.
+///
+/// Overrides may be nested; dropping a guard restores the configuration that
+/// was visible when it was created. Other threads, including those spawned
+/// while the override is active, continue to see the global configuration.
+///
+/// # Example
+///
+/// ```
+/// # use utility_belt::config::{Config, override_global};
+/// {
+///     let _guard = override_global(Config::default().set_default("a", "1"));
+///     assert_eq!(Config::global()["a"], "1");
+/// }
+/// assert!(Config::try_global().is_none());
+/// ```
+pub fn override_global(conf: impl Into<Arc<Config>>) -> GlobalOverride {
+    let depth = OVERRIDES.with(|o| {
+        let mut overrides = o.borrow_mut();
+        overrides.push(conf.into());
+        overrides.len() - 1
+    });
+
+    GlobalOverride { depth, _not_send: PhantomData }
+}
+
+/// Restores the previous global configuration for the current thread when
+/// dropped.
+///
+/// See [override_global].
+#[must_use = "The override is removed when the guard is dropped"]
+#[derive(Debug)]
+pub struct GlobalOverride {
+    depth: usize,
+    // The override is thread-local, so the guard must stay on its thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for GlobalOverride {
+    fn drop(&mut self) {
+        OVERRIDES.with(|o| o.borrow_mut().truncate(self.depth));
+    }
+}
+
+impl Config {
+    /// Retrieve the global [Config] instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no global configuration has been initialized. Use [set_global]
+    /// to register a global `Config`, or [Config::try_global] to check whether
+    /// one exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::{Config, set_global};
+    /// set_global(Config::default());
+    /// let conf = Config::global();
+    /// ```
+    pub fn global() -> Arc<Config> {
+        Self::try_global().expect("Global Config is not initialized")
+    }
+
+    /// Retrieve the global [Config] instance, or `None` if no global
+    /// configuration has been initialized.
+    pub fn try_global() -> Option<Arc<Config>> {
+        OVERRIDES.with(|o| o.borrow().last().cloned())
+            .or_else(|| {
+                GLOBAL.read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    // The tests share the process-wide configuration, so only replace_global
+    // may set it.
+
+    #[test]
+    fn override_is_thread_local() {
+        let _guard = override_global(Config::default().set_default("a", "1"));
+        assert_eq!(Config::global()["a"], "1");
+
+        std::thread::spawn(|| {
+            let _guard = override_global(
+                Config::default().set_default("a", "2")
+            );
+            assert_eq!(Config::global()["a"], "2");
+        }).join().unwrap();
+
+        assert_eq!(Config::global()["a"], "1");
+    }
+
+    #[test]
+    fn nested_overrides_are_restored() {
+        let outer = override_global(Config::default().set_default("a", "1"));
+
+        {
+            let _inner = override_global(
+                Config::default().set_default("a", "2")
+            );
+            assert_eq!(Config::global()["a"], "2");
+        }
+
+        assert_eq!(Config::global()["a"], "1");
+        drop(outer);
+
+        let restored = Config::try_global()
+            .and_then(|c| c.get_default("a").cloned());
+        assert_eq!(restored, None);
+    }
+
+    #[test]
+    fn replace_global() {
+        let conf = Arc::new(Config::default().set_default("b", "1"));
+        set_global(conf.clone());
+
+        let old = set_global(Config::default().set_default("b", "2"));
+        assert!(Arc::ptr_eq(&old.unwrap(), &conf));
+
+        let other = std::thread::spawn(Config::try_global).join().unwrap();
+        assert_eq!(other.unwrap().get_default("b"), Some(&"2".to_string()));
+    }
+}
@@ -0,0 +1,303 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Included files and configuration directories
+//!
+//! With [ParserOptions::includes] enabled, each value in the `[include]` group
+//! of a configuration file is the path of another INI file to read, relative
+//! to the directory of the including file. Included files are read in order
+//! and merged as with [Config::merge_with]; values in the including file take
+//! precedence over those it includes. The `[include]` group itself is not
+//! kept.
+//!
+//! ```ini
+//! [include]
+//! common = ../common.ini
+//! local = local.ini
+//!
+//! [Server]
+//! port = 8080
+//! ```
+//!
+//! Includes are disabled by default, so that existing files with a group named
+//! `include` keep their meaning. They are only resolved when reading from a
+//! file; parsing from a string or reader leaves the `[include]` group as
+//! ordinary values.
+//!
+//! ```
+//! # use std::path::Path;
+//! # use utility_belt::config::{Config, ParserOptions};
+//! let options = ParserOptions::new().includes(true);
+//! let conf = Config::read_from_file_blocking_with(
+//!     Path::new("tests/include/main.ini"),
+//!     &options
+//! ).unwrap();
+//!
+//! assert_eq!(conf["var2"], "common");
+//! ```
+//!
+//! [Config::read_from_dir_blocking] reads every `*.ini` file in a directory
+//! (such as `conf.d`), merging them in order of their file names.
+
+use std::path::{Path, PathBuf};
+
+use super::{Config, FileError, ParserOptions};
+
+
+/// The name of the group listing included files.
+const INCLUDE_GROUP: &str = "include";
+
+impl Config {
+    /// Read every `*.ini` file in the specified directory, merging them in
+    /// sorted order of their file names.
+    ///
+    /// Files without an `ini` extension and subdirectories are ignored.
+    ///
+    /// # Returns
+    ///
+    /// Returns the merged configuration if every file was successfully read;
+    /// otherwise returns a list of errors from all of the files.
+    pub fn read_from_dir_blocking(dir: &Path) -> Result<Self, Vec<FileError>> {
+        Self::read_from_dir_blocking_with(dir, &ParserOptions::default())
+    }
+
+    /// Read every `*.ini` file in the specified directory, using the dialect
+    /// described by `options`.
+    ///
+    /// See [Config::read_from_dir_blocking] for more information.
+    pub fn read_from_dir_blocking_with(dir: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        let io_err = |e: std::io::Error| {
+            vec![FileError::IO((dir.into(), e.kind()))]
+        };
+
+        let mut files = vec![];
+
+        for entry in std::fs::read_dir(dir).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+
+            if path.extension() == Some("ini".as_ref()) && path.is_file() {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+
+        let mut conf = Config::default().with_parser_options(options.clone());
+        let mut errors = vec![];
+
+        for file in files {
+            match Self::read_from_file_blocking_with(&file, options) {
+                Ok(c) => conf = conf.merge_with(c),
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(conf)
+        } else {
+            Err(errors)
+        }
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously read every `*.ini` file in the specified directory,
+    /// using the async-std runtime.
+    ///
+    /// See [Config::read_from_dir_blocking] for more information.
+    pub async fn read_from_dir(dir: &Path) -> Result<Self, Vec<FileError>> {
+        Self::read_from_dir_with(dir, &ParserOptions::default()).await
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously read every `*.ini` file in the specified directory,
+    /// using the dialect described by `options` and the async-std runtime.
+    ///
+    /// See [Config::read_from_dir_blocking] for more information.
+    pub async fn read_from_dir_with(dir: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        let dir = dir.to_owned();
+        let options = options.clone();
+
+        async_std::task::spawn_blocking(
+            move || Self::read_from_dir_blocking_with(&dir, &options)
+        ).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously read every `*.ini` file in the specified directory,
+    /// using the tokio runtime.
+    ///
+    /// See [Config::read_from_dir_blocking] for more information.
+    pub async fn read_from_dir_tokio(dir: &Path)
+    -> Result<Self, Vec<FileError>> {
+        Self::read_from_dir_tokio_with(dir, &ParserOptions::default()).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously read every `*.ini` file in the specified directory,
+    /// using the dialect described by `options` and the tokio runtime.
+    ///
+    /// See [Config::read_from_dir_blocking] for more information.
+    pub async fn read_from_dir_tokio_with(dir: &Path, options: &ParserOptions)
+    -> Result<Self, Vec<FileError>> {
+        let owned = dir.to_owned();
+        let options = options.clone();
+
+        super::spawn_blocking_tokio(
+            dir,
+            move || Self::read_from_dir_blocking_with(&owned, &options)
+        ).await.unwrap_or_else(|e| Err(vec![e]))
+    }
+}
+
+/// Read the file at `path`, resolving its includes if enabled by `options`.
+///
+/// `chain` lists the files that (directly or indirectly) include this one.
+pub(super) fn read_file(
+    path: &Path,
+    options: &ParserOptions,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Config, Vec<FileError>> {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.into());
+
+    let chain_to = |msg: String| {
+        let mut chain = chain.clone();
+        chain.push(path.into());
+        vec![FileError::Include { msg, chain }]
+    };
+
+    if chain.iter().any(|p| canonical(p) == canonical(path)) {
+        return Err(chain_to("Include cycle".into()));
+    }
+
+    let mut conf = match Config::read_single_file(path, options) {
+        Ok(conf) => conf,
+        Err(errs) => return match &errs[..] {
+            [FileError::IO((_, kind))] if ! chain.is_empty() => Err(
+                chain_to(format!("Cannot read included file ({:?})", kind))
+            ),
+            _ => Err(errs),
+        },
+    };
+
+    if ! options.includes { return Ok(conf); }
+
+    let includes: Vec<String> = conf.values.iter()
+        .filter(|(k, _)| k.0 == INCLUDE_GROUP)
+        .flat_map(|(_, v)| {
+            if v.list.is_empty() { vec![v.val.clone()] } else { v.list.clone() }
+        })
+        .collect();
+
+    if includes.is_empty() { return Ok(conf); }
+
+    conf.values.retain(|k, _| k.0 != INCLUDE_GROUP);
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut base = Config::default().with_parser_options(options.clone());
+    let mut errors = vec![];
+
+    chain.push(path.into());
+
+    for include in includes {
+        match read_file(&dir.join(include), options, chain) {
+            Ok(c) => base = base.merge_with(c),
+            Err(e) => errors.extend(e),
+        }
+    }
+
+    chain.pop();
+
+    if errors.is_empty() {
+        Ok(base.merge_with(conf))
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn read_with_includes(path: &str) -> Result<Config, Vec<FileError>> {
+        let options = ParserOptions::new().includes(true);
+        Config::read_from_file_blocking_with(Path::new(path), &options)
+    }
+
+    #[test]
+    fn resolve_includes() {
+        let conf = read_with_includes("tests/include/main.ini").unwrap();
+
+        assert_eq!(conf["var1"], "main");
+        assert_eq!(conf["var2"], "common");
+        assert_eq!(conf[("Group A", "var3")], "extra");
+        assert_eq!(conf[("Group B", "var4")], "base");
+        assert!(conf.groups().all(|g| g != "include"));
+    }
+
+    #[test]
+    fn include_cycle_is_err() {
+        let errs = read_with_includes("tests/include/cycle_a.ini").unwrap_err();
+
+        match &errs[..] {
+            [FileError::Include { msg, chain }] => {
+                assert!(msg.contains("cycle"));
+                assert_eq!(chain, &[
+                    PathBuf::from("tests/include/cycle_a.ini"),
+                    PathBuf::from("tests/include/cycle_b.ini"),
+                    PathBuf::from("tests/include/cycle_a.ini"),
+                ]);
+            },
+            _ => panic!("Expected a FileError::Include; got {:?}", errs),
+        }
+    }
+
+    #[test]
+    fn missing_include_is_err() {
+        let errs = read_with_includes("tests/include/missing.ini").unwrap_err();
+
+        match &errs[..] {
+            [FileError::Include { msg, chain }] => {
+                assert!(msg.contains("NotFound"));
+                assert_eq!(chain, &[
+                    PathBuf::from("tests/include/missing.ini"),
+                    PathBuf::from("tests/include/nonexistent.ini"),
+                ]);
+            },
+            _ => panic!("Expected a FileError::Include; got {:?}", errs),
+        }
+    }
+
+    #[test]
+    fn includes_are_disabled_by_default() {
+        let conf = Config::read_from_file_blocking(
+            Path::new("tests/include/missing.ini")
+        ).unwrap();
+        assert_eq!(conf[("include", "file")], "nonexistent.ini");
+    }
+
+    #[test]
+    fn parsed_text_does_not_include() {
+        let conf: Config = "[include]\nfile = nonexistent.ini\n".parse()
+            .unwrap();
+        assert_eq!(conf[("include", "file")], "nonexistent.ini");
+    }
+
+    #[test]
+    fn read_directory_in_order() {
+        let conf = Config::read_from_dir_blocking(Path::new("tests/conf.d"))
+            .unwrap();
+
+        assert_eq!(conf["var1"], "base");
+        assert_eq!(conf[("Group A", "var2")], "override");
+        assert_eq!(conf[("Group A", "var3")], "base");
+    }
+
+    #[test]
+    fn nonexistent_directory_is_err() {
+        assert!(Config::read_from_dir_blocking(Path::new("nopath")).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Value interpolation
+//!
+//! Values may reference other values:
+//!
+//! - `${var}` expands to the value of `var` in the same group, or in the
+//!   DEFAULT group if it is not set in the same group.
+//! - `${group:var}` expands to the value of `var` in `group`. If the group
+//!   name contains a colon, the variable name begins after the last colon.
+//! - `${env:NAME}` expands to the value of the environment variable `NAME`.
+//! - `$$` expands to a literal `$`; a `$` that is not followed by `{` or `$` is
+//!   kept as-is.
+//!
+//! Referenced values are themselves interpolated.
+
+use std::{
+    collections::HashMap,
+    fmt,
+};
+
+use super::{Config, Key};
+
+
+/// Error for references that cannot be interpolated.
+///
+/// Each variant includes the chain of variables that were being expanded,
+/// beginning with the variable originally requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpolationError {
+    /// The variables reference each other in a cycle; the last entry of the
+    /// chain is the variable that closes the cycle.
+    Cycle { chain: Vec<(String, String)> },
+    /// A referenced variable is not set.
+    Missing { chain: Vec<(String, String)>, reference: String },
+    /// A referenced environment variable is not set or is not valid Unicode.
+    MissingEnv { chain: Vec<(String, String)>, name: String },
+    /// A reference is not terminated by a closing brace.
+    Unterminated { chain: Vec<(String, String)> },
+}
+
+impl InterpolationError {
+    /// The chain of variables being expanded when the error occurred.
+    pub fn chain(&self) -> &[(String, String)] {
+        match *self {
+            Self::Cycle { ref chain } => chain,
+            Self::Missing { ref chain, .. } => chain,
+            Self::MissingEnv { ref chain, .. } => chain,
+            Self::Unterminated { ref chain } => chain,
+        }
+    }
+}
+
+impl fmt::Display for InterpolationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Cycle { .. } => write!(f, "Reference cycle")?,
+            Self::Missing { ref reference, .. } =>
+                write!(f, "Reference to unset variable '{}'", reference)?,
+            Self::MissingEnv { ref name, .. } =>
+                write!(f, "Reference to unset environment variable '{}'",
+                    name)?,
+            Self::Unterminated { .. } =>
+                write!(f, "Missing closing brace in reference")?,
+        }
+
+        write!(f, ": ")?;
+
+        for (i, (group, var)) in self.chain().iter().enumerate() {
+            if i > 0 { write!(f, " -> ")?; }
+            write!(f, "[{}] {}", group, var)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for InterpolationError {}
+
+impl Config {
+    /// Retrieve the value of the specified variable with all references
+    /// expanded, or `None` if it is not set.
+    ///
+    /// See the [interpolate module](super::interpolate) documentation for the
+    /// reference syntax.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let conf = Config::default()
+    ///     .set_default("base", "/srv/app")
+    ///     .set("Logs", "dir", "${base}/logs")
+    ///     .set("Logs", "file", "${dir}/app.log");
+    ///
+    /// assert_eq!(
+    ///     conf.get_interpolated("Logs", "file").unwrap().unwrap(),
+    ///     "/srv/app/logs/app.log"
+    /// );
+    /// ```
+    pub fn get_interpolated(&self, group: &str, variable: &str)
+    -> Result<Option<String>, InterpolationError> {
+        if self.get(group, variable).is_none() { return Ok(None); }
+
+        Interpolator::new(self)
+            .expand((group.into(), variable.into()))
+            .map(Some)
+    }
+
+    /// Expand the references in every value of the configuration.
+    ///
+    /// # Returns
+    ///
+    /// Returns the interpolated configuration, or a list of errors for every
+    /// value that could not be expanded.
+    pub fn interpolate(mut self) -> Result<Self, Vec<InterpolationError>> {
+        let mut interpolator = Interpolator::new(&self);
+        let mut expanded = vec![];
+        let mut errors = vec![];
+
+        for (key, _) in self.values.iter() {
+            match interpolator.expand(key.clone()) {
+                Ok(val) => expanded.push(val),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if ! errors.is_empty() { return Err(errors); }
+
+        for ((_, value), val) in self.values.entries.iter_mut().zip(expanded) {
+            value.val = val;
+        }
+
+        Ok(self)
+    }
+}
+
+/// Expands references, caching the values it has already expanded.
+struct Interpolator<'a> {
+    conf: &'a Config,
+    cache: HashMap<Key, String>,
+    chain: Vec<Key>,
+}
+
+impl<'a> Interpolator<'a> {
+    fn new(conf: &'a Config) -> Self {
+        Self { conf, cache: HashMap::new(), chain: vec![] }
+    }
+
+    /// Expand the value of the specified variable, which must be set.
+    fn expand(&mut self, key: Key) -> Result<String, InterpolationError> {
+        if let Some(val) = self.cache.get(&key) {
+            return Ok(val.clone());
+        }
+
+        if self.chain.contains(&key) {
+            let mut chain = self.chain.clone();
+            chain.push(key);
+            return Err(InterpolationError::Cycle { chain });
+        }
+
+        let raw = self.conf.get(&key.0, &key.1)
+            .expect("Interpolated variables must be set");
+
+        self.chain.push(key.clone());
+        let res = self.expand_str(raw, &key.0);
+        self.chain.pop();
+
+        let val = res?;
+        self.cache.insert(key, val.clone());
+        Ok(val)
+    }
+
+    /// Expand the references within `raw`, a value in `group`.
+    fn expand_str(&mut self, raw: &str, group: &str)
+    -> Result<String, InterpolationError> {
+        let mut val = String::with_capacity(raw.len());
+        let mut rest = raw;
+
+        while let Some(pos) = rest.find('$') {
+            val.push_str(&rest[..pos]);
+            rest = &rest[pos+1..];
+
+            if let Some(r) = rest.strip_prefix('$') {
+                val.push('$');
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('{') {
+                let end = r.find('}').ok_or_else(||
+                    InterpolationError::Unterminated { chain: self.chain() }
+                )?;
+
+                val.push_str(&self.resolve(&r[..end], group)?);
+                rest = &r[end+1..];
+            } else {
+                val.push('$');
+            }
+        }
+
+        val.push_str(rest);
+        Ok(val)
+    }
+
+    /// Resolve a single reference made from within `group`.
+    fn resolve(&mut self, reference: &str, group: &str)
+    -> Result<String, InterpolationError> {
+        if let Some(name) = reference.strip_prefix("env:") {
+            return std::env::var(name)
+                .map_err(|_| InterpolationError::MissingEnv {
+                    chain: self.chain(),
+                    name: name.into(),
+                });
+        }
+
+        let key = match reference.rsplit_once(':') {
+            Some((g, v)) => Some((g, v)),
+            None => [group, "DEFAULT"].iter()
+                .map(|g| (*g, reference))
+                .find(|(g, v)| self.conf.get(g, v).is_some()),
+        };
+
+        match key {
+            Some((g, v)) if self.conf.get(g, v).is_some() =>
+                self.expand((g.into(), v.into())),
+            _ => Err(InterpolationError::Missing {
+                chain: self.chain(),
+                reference: reference.into(),
+            }),
+        }
+    }
+
+    fn chain(&self) -> Vec<(String, String)> {
+        self.chain.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::ENV_LOCK;
+
+
+    fn key(group: &str, var: &str) -> (String, String) {
+        (group.into(), var.into())
+    }
+
+    #[test]
+    fn interpolate_references() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::set_var("UB_INTERPOLATE_TEST", "from env");
+
+        let conf = Config::default()
+            .set_default("base", "/srv")
+            .set("Paths", "data", "${base}/data")
+            .set("Paths", "cache", "${data}/cache")
+            .set("Other", "cache", "${Paths:cache}/other")
+            .set("Other", "env", "${env:UB_INTERPOLATE_TEST}")
+            .set("Other", "literal", "$$HOME costs $5")
+            .interpolate()
+            .unwrap();
+
+        assert_eq!(conf[("Paths", "data")], "/srv/data");
+        assert_eq!(conf[("Paths", "cache")], "/srv/data/cache");
+        assert_eq!(conf[("Other", "cache")], "/srv/data/cache/other");
+        assert_eq!(conf[("Other", "env")], "from env");
+        assert_eq!(conf[("Other", "literal")], "$HOME costs $5");
+    }
+
+    #[test]
+    fn group_names_may_contain_colons() {
+        let conf = Config::default()
+            .set("db:staging", "host", "example.com")
+            .set_default("url", "http://${db:staging:host}/");
+
+        assert_eq!(
+            conf.get_interpolated("DEFAULT", "url").unwrap().unwrap(),
+            "http://example.com/"
+        );
+        assert_eq!(conf.get_interpolated("DEFAULT", "none").unwrap(), None);
+    }
+
+    #[test]
+    fn cycles_are_err() {
+        let conf = Config::default()
+            .set("A", "x", "${B:y}")
+            .set("B", "y", "${z}")
+            .set("B", "z", "${A:x}");
+
+        let err = conf.get_interpolated("A", "x").unwrap_err();
+        assert_eq!(err, InterpolationError::Cycle {
+            chain: vec![key("A", "x"), key("B", "y"), key("B", "z"),
+                key("A", "x")],
+        });
+        assert_eq!(
+            err.to_string(),
+            "Reference cycle: [A] x -> [B] y -> [B] z -> [A] x"
+        );
+
+        assert_eq!(conf.interpolate().unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn dangling_references_are_err() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let errs = Config::default()
+            .set("A", "x", "${y}")
+            .set("A", "y", "${B:nothing}")
+            .set("A", "env", "${env:UB_INTERPOLATE_NOT_SET}")
+            .set("A", "open", "${y")
+            .set("A", "fine", "value")
+            .interpolate()
+            .unwrap_err();
+
+        assert_eq!(errs, vec![
+            InterpolationError::Missing {
+                chain: vec![key("A", "x"), key("A", "y")],
+                reference: "B:nothing".into(),
+            },
+            InterpolationError::Missing {
+                chain: vec![key("A", "y")],
+                reference: "B:nothing".into(),
+            },
+            InterpolationError::MissingEnv {
+                chain: vec![key("A", "env")],
+                name: "UB_INTERPOLATE_NOT_SET".into(),
+            },
+            InterpolationError::Unterminated {
+                chain: vec![key("A", "open")],
+            },
+        ]);
+    }
+}
@@ -0,0 +1,249 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! List values
+//!
+//! A list can be written either by assigning a variable repeatedly or as a
+//! single delimiter-separated value:
+//!
+//! ```ini
+//! [Repeated]
+//! server = a.example.com
+//! server = b.example.com
+//!
+//! [Delimited]
+//! servers = a.example.com, b.example.com
+//! ```
+//!
+//! [Config::get] returns a single value of a repeated variable (the last, by
+//! default; see [Duplicates](super::Duplicates)), while [Config::get_list]
+//! returns them all. The [ListStyle] of the configuration's [ParserOptions]
+//! determines how `get_list` reads and [Config::set_list] writes a list;
+//! [Config::get_list_with] reads a list in a specific style.
+//!
+//! An empty value is an empty list.
+
+use super::{Config, Duplicates, Origin, ParserOptions, Value};
+
+
+/// How a list is stored in a configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListStyle {
+    /// Each item is a separate assignment to the variable.
+    Repeated,
+    /// The items are separated by the given character in a single value;
+    /// whitespace surrounding each item is removed.
+    Delimited(char),
+}
+
+impl Config {
+    /// Retrieve the list of values of the specified variable within the
+    /// specified group, or `None` if it is not set.
+    ///
+    /// The list is read in the [ListStyle] of the configuration's
+    /// [ParserOptions].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let conf: Config = "server = a\nserver = b\n".parse().unwrap();
+    ///
+    /// assert_eq!(conf["server"], "b");
+    /// assert_eq!(conf.get_list("DEFAULT", "server").unwrap(), vec!["a", "b"]);
+    /// ```
+    pub fn get_list(&self, group: &str, variable: &str) -> Option<Vec<&str>> {
+        self.get_list_with(group, variable, self.options.list_style)
+    }
+
+    /// Retrieve the list of values of the specified variable within the
+    /// specified group, reading the list in the given [ListStyle].
+    ///
+    /// If a variable is assigned more than once, every assignment contributes
+    /// to the list regardless of the style.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::{Config, ListStyle};
+    /// let conf: Config = "ports = 80, 443\n".parse().unwrap();
+    /// let style = ListStyle::Delimited(',');
+    ///
+    /// assert_eq!(
+    ///     conf.get_list_with("DEFAULT", "ports", style).unwrap(),
+    ///     vec!["80", "443"]
+    /// );
+    /// ```
+    pub fn get_list_with(&self, group: &str, variable: &str, style: ListStyle)
+    -> Option<Vec<&str>> {
+        let value = self.value(group, variable)?;
+
+        let assigned: Vec<&str> = if value.list.is_empty() {
+            vec![&value.val]
+        } else {
+            value.list.iter().map(String::as_str).collect()
+        };
+
+        let list = match style {
+            ListStyle::Repeated if assigned == [""] => vec![],
+            ListStyle::Repeated => assigned,
+            ListStyle::Delimited(delim) => assigned.into_iter()
+                .filter(|val| ! val.is_empty())
+                .flat_map(|val| val.split(delim).map(str::trim))
+                .collect(),
+        };
+
+        Some(list)
+    }
+
+    /// Set the specified variable to a list of values, written in the
+    /// [ListStyle] of the configuration's [ParserOptions].
+    ///
+    /// With [ListStyle::Delimited], items containing the delimiter cannot be
+    /// read back unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let conf = Config::default().set_list("Mirrors", "url", &["a", "b"]);
+    ///
+    /// assert_eq!(conf.to_string(), "[Mirrors]\nurl = a\nurl = b\n");
+    /// ```
+    pub fn set_list(mut self, group: &str, variable: &str, items: &[&str])
+    -> Self {
+        let value = list_value(items, &self.options);
+        self.values.insert(self.key(group, variable), value);
+        self
+    }
+}
+
+/// Build the [Value] storing a list in the style described by `options`.
+fn list_value(items: &[&str], options: &ParserOptions) -> Value {
+    match options.list_style {
+        ListStyle::Repeated => {
+            let val = match options.duplicates {
+                Duplicates::First => items.first(),
+                _ => items.last(),
+            };
+
+            let mut value = Value::new(
+                val.copied().unwrap_or_default().into(),
+                Origin::Set
+            );
+            if items.len() > 1 {
+                value.list = items.iter().map(|i| i.to_string()).collect();
+            }
+            value
+        },
+        ListStyle::Delimited(delim) => Value::new(
+            items.join(&delim.to_string()),
+            Origin::Set
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn repeated_assignments() {
+        let conf: Config = "\
+[A]
+a = 1
+b = x
+a = 2
+    more
+a = \" 3 \"
+empty =
+".parse().unwrap();
+
+        assert_eq!(conf[("A", "a")], " 3 ");
+        assert_eq!(
+            conf.get_list("A", "a").unwrap(),
+            vec!["1", "2\nmore", " 3 "]
+        );
+        assert_eq!(conf.get_list("A", "b").unwrap(), vec!["x"]);
+        assert!(conf.get_list("A", "empty").unwrap().is_empty());
+        assert!(conf.get_list("A", "missing").is_none());
+
+        assert_eq!(
+            conf.get_list_with("A", "a", ListStyle::Delimited(' ')).unwrap(),
+            vec!["1", "2\nmore", "", "3", ""]
+        );
+    }
+
+    #[test]
+    fn first_value_is_kept() {
+        let options = ParserOptions::new().duplicates(Duplicates::First);
+        let conf = Config::from_reader_with(
+            "a = 1\n    one\na = 2\n    two\n".as_bytes(),
+            "<string>",
+            &options
+        ).unwrap();
+
+        assert_eq!(conf["a"], "1\none");
+        assert_eq!(
+            conf.get_list("DEFAULT", "a").unwrap(),
+            vec!["1\none", "2\ntwo"]
+        );
+        assert_eq!(
+            conf.to_string(),
+            "[DEFAULT]\na = 1\n    one\na = 2\n    two\n"
+        );
+    }
+
+    #[test]
+    fn delimited_lists() {
+        let options = ParserOptions::new()
+            .list_style(ListStyle::Delimited(','));
+        let conf = Config::from_reader_with(
+            "a = x, y ,z\nb = 1\nb = 2, 3\nc =\n".as_bytes(),
+            "<string>",
+            &options
+        ).unwrap();
+
+        assert_eq!(conf.get_list("DEFAULT", "a").unwrap(), vec!["x", "y", "z"]);
+        assert_eq!(
+            conf.get_list("DEFAULT", "b").unwrap(),
+            vec!["1", "2", "3"]
+        );
+        assert!(conf.get_list("DEFAULT", "c").unwrap().is_empty());
+        assert_eq!(
+            conf.get_list_with("DEFAULT", "a", ListStyle::Repeated).unwrap(),
+            vec!["x, y ,z"]
+        );
+    }
+
+    #[test]
+    fn lists_round_trip() {
+        for style in &[ListStyle::Repeated, ListStyle::Delimited(',')] {
+            let options = ParserOptions::new().list_style(*style);
+            let conf = Config::default()
+                .with_parser_options(options.clone())
+                .set_list("G", "one", &["a"])
+                .set_list("G", "many", &["a", " b ", "c\nd"])
+                .set_list("G", "none", &[]);
+
+            let text = conf.to_string();
+            let read = Config::from_reader_with(
+                text.as_bytes(), "<string>", &options
+            ).unwrap();
+
+            for var in &["one", "many", "none"] {
+                assert_eq!(
+                    read.get_list("G", var),
+                    conf.get_list("G", var),
+                    "{:?} {} in:\n{}", style, var, text
+                );
+            }
+        }
+
+        let conf = Config::default().set_list("G", "v", &["a", "b"]);
+        assert_eq!(conf[("G", "v")], "b");
+        assert_eq!(conf.to_string(), "[G]\nv = a\nv = b\n");
+    }
+}
@@ -0,0 +1,470 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Layered configuration discovery
+//!
+//! A [ConfigLoader] builds a [Config] from a list of layers; each layer
+//! overrides the values set by the layers declared before it. The file layers
+//! look for a file named `{name}.ini` (see [ConfigLoader::file_name]) in the
+//! locations given by the [XDG base directory specification][xdg]:
+//!
+//! | Layer                   | Files read, in order                          |
+//! |-------------------------|-----------------------------------------------|
+//! | [system][S]             | `/etc/{name}/{name}.ini`, then                |
+//! |                         | `{dir}/{name}/{name}.ini` for each `dir` in   |
+//! |                         | `$XDG_CONFIG_DIRS` (default `/etc/xdg`), the  |
+//! |                         | most important directory last                 |
+//! | [user][U]               | `$XDG_CONFIG_HOME/{name}/{name}.ini` (default |
+//! |                         | `$HOME/.config/{name}/{name}.ini`)            |
+//! | [project][P]            | `{name}.ini` in the current directory         |
+//! | [file][F]               | The specified path                            |
+//!
+//! File layers are optional unless marked [required][R]: a missing optional
+//! file is skipped, while a missing required file is an error. Files that
+//! exist but cannot be read or parsed are always errors.
+//!
+//! The [env][E] and [args][A] layers merge environment variables and
+//! command-line arguments as with [Config::merge_env] and [Config::merge_args].
+//!
+//! [xdg]: https://specifications.freedesktop.org/basedir-spec/latest/
+//! [S]: ConfigLoader::system
+//! [U]: ConfigLoader::user
+//! [P]: ConfigLoader::project
+//! [F]: ConfigLoader::file
+//! [R]: ConfigLoader::required
+//! [E]: ConfigLoader::env
+//! [A]: ConfigLoader::args
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{Config, EnvOptions, FileError, ParserOptions};
+
+
+/// Where a layer's values come from.
+#[derive(Debug, Clone)]
+enum Source {
+    System,
+    User,
+    Project,
+    File(PathBuf),
+    Env(String, EnvOptions),
+    Args(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    source: Source,
+    required: bool,
+}
+
+/// Builds a [Config] from system, user, and project configuration files,
+/// the environment, and the command line.
+///
+/// See the [loader module](super::loader) documentation for the locations
+/// searched by each layer.
+///
+/// # Example
+///
+/// A typical application reads the system, user, and project layers, then the
+/// environment and command line:
+///
+/// ```no_run
+/// # use utility_belt::config::{Config, ConfigLoader};
+/// let (conf, files) = ConfigLoader::new("myapp")
+///     .defaults(Config::default().set_default("port", "80"))
+///     .system()
+///     .user()
+///     .project()
+///     .env("MYAPP")
+///     .args(std::env::args())
+///     .load_blocking()
+///     .unwrap();
+/// ```
+///
+/// Each layer overrides those before it:
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use utility_belt::config::{Config, ConfigLoader};
+/// let (conf, files) = ConfigLoader::new("myapp")
+///     .defaults(Config::default().set("Group", "port", "80"))
+///     .file("tests/loader/explicit.ini")
+///     .required()
+///     .args(["prog", "--set", "Group:port=8080"].iter())
+///     .load_blocking()
+///     .unwrap();
+///
+/// assert_eq!(conf[("Group", "port")], "8080");
+/// assert_eq!(conf[("Group", "explicit")], "explicit");
+/// assert_eq!(files, vec![PathBuf::from("tests/loader/explicit.ini")]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    name: String,
+    file_name: String,
+    defaults: Config,
+    parser_options: ParserOptions,
+    layers: Vec<Layer>,
+}
+
+impl ConfigLoader {
+    /// Create a loader for the application called `name`, with no layers.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            file_name: format!("{}.ini", name),
+            defaults: Config::default(),
+            parser_options: ParserOptions::default(),
+            layers: vec![],
+        }
+    }
+
+    /// Set the name of the configuration file searched for by the system,
+    /// user, and project layers. The default is `{name}.ini`.
+    pub fn file_name(mut self, file_name: &str) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    /// Set the configuration that the layers are merged into.
+    ///
+    /// Setting default values ensures that environment variables map to the
+    /// intended names; see the [env module](super::env) documentation.
+    pub fn defaults(mut self, conf: Config) -> Self {
+        self.defaults = conf;
+        self
+    }
+
+    /// Set the [ParserOptions] used to read the configuration files.
+    pub fn parser_options(mut self, options: ParserOptions) -> Self {
+        self.parser_options = options;
+        self
+    }
+
+    /// Add a layer reading the system-wide configuration files.
+    pub fn system(self) -> Self {
+        self.layer(Source::System)
+    }
+
+    /// Add a layer reading the user's configuration file.
+    pub fn user(self) -> Self {
+        self.layer(Source::User)
+    }
+
+    /// Add a layer reading the configuration file in the current directory.
+    pub fn project(self) -> Self {
+        self.layer(Source::Project)
+    }
+
+    /// Add a layer reading the file at `path`.
+    pub fn file(self, path: impl Into<PathBuf>) -> Self {
+        self.layer(Source::File(path.into()))
+    }
+
+    /// Add a layer merging environment variables that start with `{prefix}_`.
+    pub fn env(self, prefix: &str) -> Self {
+        self.env_with(prefix, &EnvOptions::default())
+    }
+
+    /// Add a layer merging environment variables, using the specified
+    /// [EnvOptions].
+    pub fn env_with(self, prefix: &str, options: &EnvOptions) -> Self {
+        self.layer(Source::Env(prefix.into(), options.clone()))
+    }
+
+    /// Add a layer merging `--set` command-line arguments.
+    pub fn args<I, S>(self, args: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>,
+    {
+        let args = args.into_iter().map(|a| a.as_ref().to_string()).collect();
+        self.layer(Source::Args(args))
+    }
+
+    /// Require that the most recently added layer reads at least one file.
+    ///
+    /// This has no effect on the env and args layers.
+    pub fn required(mut self) -> Self {
+        if let Some(layer) = self.layers.last_mut() {
+            layer.required = true;
+        }
+        self
+    }
+
+    /// Read and merge every layer.
+    ///
+    /// # Returns
+    ///
+    /// Returns the merged configuration and the list of files that were read,
+    /// in the order they were read; otherwise returns a list of errors from
+    /// all of the layers.
+    pub fn load_blocking(&self)
+    -> Result<(Config, Vec<PathBuf>), Vec<FileError>> {
+        let mut conf = self.defaults.clone()
+            .with_parser_options(self.parser_options.clone());
+        let mut files = vec![];
+        let mut errors = vec![];
+
+        for layer in &self.layers {
+            let candidates = match layer.source {
+                Source::Env(ref prefix, ref options) => {
+                    conf = conf.merge_env_with(prefix, options);
+                    continue;
+                },
+                Source::Args(ref args) => {
+                    match Config::default().merge_args(args) {
+                        Ok(c) => conf = conf.merge_with(c),
+                        Err(e) => errors.extend(e),
+                    }
+                    continue;
+                },
+                ref source => self.candidates(source),
+            };
+
+            let found: Vec<_> = candidates.iter()
+                .filter(|p| p.is_file())
+                .collect();
+
+            if found.is_empty() && layer.required {
+                let path = candidates.last().cloned()
+                    .unwrap_or_else(|| PathBuf::from(&self.file_name));
+                errors.push(FileError::IO((path, io::ErrorKind::NotFound)));
+            }
+
+            for path in found {
+                let options = &self.parser_options;
+
+                match Config::read_from_file_blocking_with(path, options) {
+                    Ok(c) => conf = conf.merge_with(c),
+                    Err(e) => errors.extend(e),
+                }
+                files.push(path.clone());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok((conf, files))
+        } else {
+            Err(errors)
+        }
+    }
+
+    #[cfg(feature = "config_async_std")]
+    /// Asynchronously read and merge every layer, using the async-std
+    /// runtime.
+    ///
+    /// See [ConfigLoader::load_blocking] for more information.
+    pub async fn load(&self)
+    -> Result<(Config, Vec<PathBuf>), Vec<FileError>> {
+        let loader = self.clone();
+        async_std::task::spawn_blocking(move || loader.load_blocking()).await
+    }
+
+    #[cfg(feature = "config_tokio")]
+    /// Asynchronously read and merge every layer, using the tokio runtime.
+    ///
+    /// See [ConfigLoader::load_blocking] for more information.
+    pub async fn load_tokio(&self)
+    -> Result<(Config, Vec<PathBuf>), Vec<FileError>> {
+        let loader = self.clone();
+
+        super::spawn_blocking_tokio(
+            Path::new(&self.file_name),
+            move || loader.load_blocking()
+        ).await.unwrap_or_else(|e| Err(vec![e]))
+    }
+
+    #[cfg(feature = "config_watch")]
+    /// Every file the file layers would read if it existed.
+    pub(super) fn paths(&self) -> Vec<PathBuf> {
+        self.layers.iter()
+            .flat_map(|layer| self.candidates(&layer.source))
+            .collect()
+    }
+
+    fn layer(mut self, source: Source) -> Self {
+        self.layers.push(Layer { source, required: false });
+        self
+    }
+
+    /// The files a file layer reads if they exist, in order.
+    fn candidates(&self, source: &Source) -> Vec<PathBuf> {
+        let in_dir = |dir: &Path| dir.join(&self.name).join(&self.file_name);
+
+        match *source {
+            Source::System => {
+                let mut files = vec![in_dir(Path::new("/etc"))];
+                let mut dirs = xdg_dirs("XDG_CONFIG_DIRS")
+                    .unwrap_or_else(|| vec![PathBuf::from("/etc/xdg")]);
+
+                // The first directory is the most important, so it is read
+                // last.
+                dirs.reverse();
+                files.extend(dirs.iter().map(|d| in_dir(d)));
+                files
+            },
+            Source::User => {
+                xdg_dirs("XDG_CONFIG_HOME")
+                    .and_then(|mut dirs| dirs.pop())
+                    .or_else(|| {
+                        let home = std::env::var_os("HOME")?;
+                        Some(Path::new(&home).join(".config"))
+                    })
+                    .map(|dir| vec![in_dir(&dir)])
+                    .unwrap_or_default()
+            },
+            Source::Project => vec![PathBuf::from(&self.file_name)],
+            Source::File(ref path) => vec![path.clone()],
+            Source::Env(..) | Source::Args(..) => vec![],
+        }
+    }
+}
+
+/// Read the list of absolute paths in the environment variable `name`.
+///
+/// Returns `None` if the variable is unset or contains no absolute paths; the
+/// specification requires relative paths to be ignored.
+fn xdg_dirs(name: &str) -> Option<Vec<PathBuf>> {
+    let var = std::env::var_os(name)?;
+
+    let dirs: Vec<_> = std::env::split_paths(&var)
+        .filter(|p| p.is_absolute())
+        .collect();
+
+    if dirs.is_empty() { None } else { Some(dirs) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ffi::{OsStr, OsString}, sync::MutexGuard};
+    use crate::config::tests::ENV_LOCK;
+
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/loader").join(path)
+    }
+
+    /// Holds [ENV_LOCK] while a test modifies environment variables, and
+    /// restores their previous values when dropped.
+    struct TestEnv {
+        saved: Vec<(&'static str, Option<OsString>)>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl TestEnv {
+        fn new() -> Self {
+            Self { saved: vec![], _lock: ENV_LOCK.lock().unwrap() }
+        }
+
+        fn set(&mut self, name: &'static str, value: impl AsRef<OsStr>) {
+            self.saved.push((name, std::env::var_os(name)));
+            std::env::set_var(name, value);
+        }
+
+        fn set_xdg_vars(&mut self) {
+            let dirs = std::env::join_paths([
+                fixture("dirs_a"),
+                PathBuf::from("relative"),
+                fixture("dirs_b"),
+            ]).unwrap();
+
+            self.set("XDG_CONFIG_DIRS", dirs);
+            self.set("XDG_CONFIG_HOME", fixture("home"));
+        }
+    }
+
+    impl Drop for TestEnv {
+        fn drop(&mut self) {
+            for (name, value) in self.saved.drain(..).rev() {
+                match value {
+                    Some(value) => std::env::set_var(name, value),
+                    None => std::env::remove_var(name),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn layers_override_in_order() {
+        let mut env = TestEnv::new();
+        env.set_xdg_vars();
+        env.set("UB_LOADER_TEST_GROUP__ENV", "env");
+
+        let (conf, files) = ConfigLoader::new("ub_loader_test")
+            .defaults(Config::default().set("Group", "env", "default"))
+            .system()
+            .user()
+            .project()
+            .file(fixture("explicit.ini"))
+            .env("UB_LOADER_TEST")
+            .args(["--set", "Group:args=args"].iter())
+            .load_blocking()
+            .unwrap();
+
+        assert_eq!(files, vec![
+            fixture("dirs_b/ub_loader_test/ub_loader_test.ini"),
+            fixture("dirs_a/ub_loader_test/ub_loader_test.ini"),
+            fixture("home/ub_loader_test/ub_loader_test.ini"),
+            fixture("explicit.ini"),
+        ]);
+
+        assert_eq!(conf[("Group", "dirs_b")], "dirs_b");
+        assert_eq!(conf[("Group", "dirs_a")], "dirs_a");
+        assert_eq!(conf[("Group", "home")], "home");
+        assert_eq!(conf[("Group", "explicit")], "explicit");
+        assert_eq!(conf[("Group", "env")], "env");
+        assert_eq!(conf[("Group", "args")], "args");
+        assert_eq!(conf[("Group", "shared")], "explicit");
+    }
+
+    #[test]
+    fn missing_required_layer_is_err() {
+        let mut env = TestEnv::new();
+        env.set("XDG_CONFIG_HOME", fixture("nonexistent"));
+
+        let loader = ConfigLoader::new("ub_loader_test")
+            .user()
+            .file(fixture("nonexistent.ini"));
+
+        assert!(loader.load_blocking().is_ok());
+
+        let errs = loader.required().load_blocking().unwrap_err();
+        match &errs[..] {
+            [FileError::IO((path, io::ErrorKind::NotFound))] =>
+                assert_eq!(path, &fixture("nonexistent.ini")),
+            _ => panic!("Expected a NotFound error; got {:?}", errs),
+        }
+    }
+
+    #[test]
+    fn invalid_files_are_err() {
+        let errs = ConfigLoader::new("ub_loader_test")
+            .file(fixture("explicit.ini"))
+            .file("tests/invalid.ini")
+            .args(["--set", "no value"].iter())
+            .load_blocking()
+            .unwrap_err();
+
+        assert!(matches!(errs[0], FileError::Parse { .. }));
+        assert!(matches!(errs.last(), Some(FileError::Argument { .. })));
+    }
+
+    #[cfg(feature = "config_async_std")]
+    #[async_std::test]
+    async fn load_async() {
+        let (conf, files) = ConfigLoader::new("ub_loader_test")
+            .file(fixture("explicit.ini"))
+            .required()
+            .load()
+            .await
+            .unwrap();
+
+        assert_eq!(conf[("Group", "explicit")], "explicit");
+        assert_eq!(files, vec![fixture("explicit.ini")]);
+    }
+}
@@ -0,0 +1,681 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The INI parser shared by all of the [Config] reading functions
+//!
+//! The parser is fed one line at a time so that it can be driven by both
+//! synchronous and asynchronous readers.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    io,
+};
+
+use super::{
+    Config,
+    FileError,
+    Key,
+    ListStyle,
+    Origin,
+    Value,
+    Values,
+    quote::unquote,
+};
+
+
+/// How to handle a variable that is set more than once in the same file.
+///
+/// Unless repeated assignments are an error, every value assigned is kept as
+/// a list; see [Config::get_list].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicates {
+    /// Keep the last value read.
+    Last,
+    /// Keep the first value read.
+    First,
+    /// Report a [FileError::Parse].
+    Error,
+}
+
+/// Options that describe the INI dialect to read and write.
+///
+/// The default options describe the dialect in the
+/// [config module](super) documentation.
+///
+/// # Example
+///
+/// ```
+/// # use utility_belt::config::{Config, ParserOptions};
+/// let options = ParserOptions::new()
+///     .comment_prefixes(&[";", "#"])
+///     .inline_comment_prefixes(&["#"])
+///     .separators(&['=', ':'])
+///     .case_sensitive(false)
+///     .default_group("general");
+///
+/// let text = "# Settings\n[General]\nName: app # the name\n\
+///     [Server]\nPort=80\n";
+/// let conf = Config::from_reader_with(text.as_bytes(), "<string>", &options)
+///     .unwrap();
+///
+/// assert_eq!(conf["name"], "app");
+/// assert_eq!(conf[("server", "port")], "80");
+/// assert_eq!(
+///     conf.to_string(),
+///     "[general]\nname = app\n[server]\nport = 80\n"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserOptions {
+    comment_prefixes: Vec<String>,
+    inline_comment_prefixes: Vec<String>,
+    pub(super) separators: Vec<char>,
+    case_sensitive: bool,
+    default_group: String,
+    pub(super) duplicates: Duplicates,
+    pub(super) list_style: ListStyle,
+    pub(super) default_fallback: bool,
+    inheritance: bool,
+    pub(super) includes: bool,
+    pub(super) continuation_lines: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            comment_prefixes: vec![";".into()],
+            inline_comment_prefixes: vec![],
+            separators: vec!['='],
+            case_sensitive: true,
+            default_group: "DEFAULT".into(),
+            duplicates: Duplicates::Last,
+            list_style: ListStyle::Repeated,
+            default_fallback: false,
+            inheritance: false,
+            includes: false,
+            continuation_lines: true,
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the prefixes that begin a comment line. The default is a
+    /// semicolon (`;`).
+    pub fn comment_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.comment_prefixes = prefixes.iter().map(|p| p.to_string())
+            .collect();
+        self
+    }
+
+    /// Set the prefixes that begin a comment at the end of a group header,
+    /// value, or continuation line. An inline comment must be preceded by
+    /// whitespace. By default, inline comments are not recognized.
+    pub fn inline_comment_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.inline_comment_prefixes = prefixes.iter()
+            .map(|p| p.to_string())
+            .collect();
+        self
+    }
+
+    /// Set the characters that separate a variable from its value; an
+    /// assignment is split at the first of them. The first separator is used
+    /// when writing. The default is an equal sign (`=`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `separators` is empty.
+    pub fn separators(mut self, separators: &[char]) -> Self {
+        assert!(! separators.is_empty(), "A separator is required");
+        self.separators = separators.into();
+        self
+    }
+
+    /// If `false`, group and variable names are converted to lower case when
+    /// they are read, set, or looked up. The default is `true`.
+    pub fn case_sensitive(mut self, sensitive: bool) -> Self {
+        self.case_sensitive = sensitive;
+        self
+    }
+
+    /// Set the name of the group in files that holds the values of the
+    /// DEFAULT group. The default is `DEFAULT`.
+    pub fn default_group(mut self, name: &str) -> Self {
+        self.default_group = name.into();
+        self
+    }
+
+    /// Set how to handle a variable set more than once in the same file. The
+    /// default is [Duplicates::Last].
+    pub fn duplicates(mut self, duplicates: Duplicates) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// Set how lists are read by [Config::get_list] and written by
+    /// [Config::set_list]. The default is [ListStyle::Repeated].
+    pub fn list_style(mut self, style: ListStyle) -> Self {
+        self.list_style = style;
+        self
+    }
+
+    /// If `true`, a variable that is not set in a group (or the groups it
+    /// inherits from) is looked up in the DEFAULT group. The default is
+    /// `false`.
+    ///
+    /// See [Config::resolve] to determine which group supplied a value.
+    pub fn default_fallback(mut self, fallback: bool) -> Self {
+        self.default_fallback = fallback;
+        self
+    }
+
+    /// If `true`, a group header of the form `[child : parent]` makes the
+    /// child group inherit the variables of the parent group that it does not
+    /// set itself; see [Config::set_parent]. The default is `false`, in which
+    /// case the colon is part of the group name.
+    pub fn inheritance(mut self, inheritance: bool) -> Self {
+        self.inheritance = inheritance;
+        self
+    }
+
+    /// If `true`, the values of the `[include]` group of a file read from disk
+    /// are the paths of further files to read; see the
+    /// [include module](super::include) documentation. The default is
+    /// `false`, in which case `[include]` is an ordinary group.
+    pub fn includes(mut self, includes: bool) -> Self {
+        self.includes = includes;
+        self
+    }
+
+    /// If `true`, a line indented more deeply than the assignment before it
+    /// continues that assignment's value, as in Python's configparser; see the
+    /// [config module](super) documentation. The default is `true`.
+    ///
+    /// Files that indent lines for readability rather than to continue values
+    /// (e.g., `x = 1` followed by an indented `[Group]` header or `y = 2`)
+    /// must be read with continuation lines disabled. Multi-line values are
+    /// then quoted when written.
+    pub fn continuation_lines(mut self, continuation_lines: bool) -> Self {
+        self.continuation_lines = continuation_lines;
+        self
+    }
+
+    /// The separator used when writing.
+    pub(super) fn separator(&self) -> char {
+        self.separators[0]
+    }
+
+    /// The name of the DEFAULT group when written.
+    pub(super) fn default_group_name(&self) -> &str {
+        &self.default_group
+    }
+
+    /// Convert a group name as written in a file to its name in a [Config].
+    pub(super) fn group_name(&self, name: &str) -> String {
+        if self.names_match(name, &self.default_group)
+            || self.names_match(name, "DEFAULT")
+        {
+            "DEFAULT".into()
+        } else {
+            self.fold(name)
+        }
+    }
+
+    /// Split a group header's name into the group's name and the name of its
+    /// parent, if any.
+    pub(super) fn group_header<'a>(&self, name: &'a str)
+    -> (&'a str, Option<&'a str>) {
+        match name.split_once(':') {
+            Some((child, parent)) if self.inheritance =>
+                (child.trim_end(), Some(parent.trim_start())),
+            _ => (name, None),
+        }
+    }
+
+    /// Convert a group or variable name to the case used by a [Config].
+    pub(super) fn fold(&self, name: &str) -> String {
+        if self.case_sensitive { name.into() } else { name.to_lowercase() }
+    }
+
+    /// Determine whether two names refer to the same group or variable.
+    pub(super) fn names_match(&self, a: &str, b: &str) -> bool {
+        a == b || (! self.case_sensitive && self.fold(a) == self.fold(b))
+    }
+
+    /// Determine whether the (trimmed) line is a comment.
+    pub(super) fn is_comment(&self, line: &str) -> bool {
+        self.comment_prefixes.iter().any(|p| line.starts_with(p.as_str()))
+    }
+
+    /// Determine whether `text` would be read as the start of a comment or
+    /// contains an inline comment.
+    pub(super) fn has_comment(&self, text: &str) -> bool {
+        self.is_comment(text)
+            || self.inline_comment_prefixes.iter()
+                .any(|p| text.contains(p.as_str()))
+    }
+
+    /// Remove an inline comment, and the whitespace preceding it, from
+    /// `text`.
+    pub(super) fn strip_inline_comment<'a>(&self, text: &'a str) -> &'a str {
+        let start = self.inline_comment_prefixes.iter()
+            .filter_map(|p| {
+                text.match_indices(p.as_str())
+                    .map(|(i, _)| i)
+                    .find(|i| text[..*i].ends_with(char::is_whitespace))
+            })
+            .min();
+
+        match start {
+            Some(i) => text[..i].trim_end(),
+            None => text,
+        }
+    }
+}
+
+/// The syntactic meaning of a single line of an INI file.
+#[derive(Debug, PartialEq)]
+pub(super) enum Line<'a> {
+    Blank,
+    Comment,
+    /// A group header, with the group's name.
+    Group(&'a str),
+    /// A variable assignment, with the variable name and the unparsed value.
+    Assignment(&'a str, &'a str),
+    /// An invalid line, with a description of the problem.
+    Invalid(&'static str),
+}
+
+/// The width, in bytes, of the leading whitespace of a line.
+pub(super) fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Determine whether `line` continues the value of an assignment whose line
+/// is indented by `assignment_indent`, returning the text to append if so.
+///
+/// A continuation line is a non-blank line indented more deeply than the
+/// assignment line, unless continuation lines are disabled by the options.
+pub(super) fn continuation<'a>(
+    line: &'a str,
+    assignment_indent: usize,
+    options: &ParserOptions,
+) -> Option<&'a str> {
+    let text = line.trim();
+
+    if options.continuation_lines
+        && ! text.is_empty()
+        && indent(line) > assignment_indent
+    {
+        Some(options.strip_inline_comment(text))
+    } else {
+        None
+    }
+}
+
+/// Determine whether `line` is a comment within the value of an assignment
+/// whose line is indented by `assignment_indent`.
+///
+/// Like Python's configparser, such comments are skipped without ending the
+/// value.
+pub(super) fn continued_comment(
+    line: &str,
+    assignment_indent: usize,
+    options: &ParserOptions,
+) -> bool {
+    options.continuation_lines
+        && indent(line) > assignment_indent
+        && options.is_comment(line.trim())
+}
+
+/// Determine the meaning of a line.
+pub(super) fn classify<'a>(line: &'a str, options: &ParserOptions)
+-> Line<'a> {
+    let line = line.trim();
+
+    if line.is_empty() {
+        Line::Blank
+    } else if options.is_comment(line) {
+        Line::Comment
+    } else if line.starts_with('[') {
+        let line = options.strip_inline_comment(line);
+
+        if line.ends_with(']') {
+            Line::Group(line[1..line.len()-1].trim())
+        } else {
+            Line::Invalid("Missing closing bracket for group name")
+        }
+    } else if let Some(i) = line.find(options.separators.as_slice()) {
+        // We'll allow empty values, but not variables.
+        let var = line[..i].trim_end();
+        let sep_len = line[i..].chars().next().map_or(1, char::len_utf8);
+
+        if var.is_empty() {
+            Line::Invalid("Assignment requires a variable name")
+        } else {
+            Line::Assignment(var, line[i+sep_len..].trim_start())
+        }
+    } else {
+        Line::Invalid("Expected a variable assignment")
+    }
+}
+
+/// Parse the value of an assignment, returning the value and whether it was
+/// quoted.
+pub(super) fn parse_value(val: &str, options: &ParserOptions)
+-> Result<(String, bool), &'static str> {
+    if val.starts_with('"') {
+        let (unquoted, rest) = unquote(val)?;
+
+        if options.strip_inline_comment(rest).trim().is_empty() {
+            Ok((unquoted, true))
+        } else {
+            Err("Unexpected text after closing quote")
+        }
+    } else {
+        Ok((options.strip_inline_comment(val).into(), false))
+    }
+}
+
+/// The inline comment following the value `val` of an assignment, including
+/// the whitespace preceding it, or an empty string if there is none.
+pub(super) fn inline_comment<'a>(val: &'a str, options: &ParserOptions)
+-> &'a str {
+    let text = if val.starts_with('"') {
+        match unquote(val) {
+            Ok((_, rest)) => rest,
+            Err(_) => return "",
+        }
+    } else {
+        val
+    };
+
+    &text[options.strip_inline_comment(text).len()..]
+}
+
+/// A line-oriented INI parser.
+pub(super) struct Parser<'a> {
+    file: PathBuf,
+    options: &'a ParserOptions,
+    group: String,
+    line: u32,
+    values: Values,
+    parents: HashMap<String, String>,
+    errors: Vec<FileError>,
+    /// The key and indentation of the assignment that continuation lines
+    /// would extend; the key is `None` if the assignment is being ignored.
+    open: Option<(Option<Key>, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    /// Create a parser for the file at the given path.
+    ///
+    /// The path is only used to describe the origin of values and errors.
+    pub(super) fn new(file: &Path, options: &'a ParserOptions) -> Self {
+        Self {
+            file: file.to_owned(),
+            options,
+            group: String::from("DEFAULT"),
+            line: 0,
+            values: Values::default(),
+            parents: HashMap::new(),
+            errors: vec![],
+            open: None,
+        }
+    }
+
+    /// Parse the next line of the file.
+    pub(super) fn parse_line(&mut self, line: &str) {
+        self.line += 1;
+
+        if let Some((ref key, assignment_indent)) = self.open {
+            if continued_comment(line, assignment_indent, self.options) {
+                return;
+            }
+
+            if let Some(text) =
+                continuation(line, assignment_indent, self.options)
+            {
+                if let Some(key) = key {
+                    let value = self.values.get_mut(key)
+                        .expect("Continued values must be set");
+
+                    // Continue the most recent assignment, which is only the
+                    // current value if it is the first or last assignment is
+                    // kept.
+                    let current = value.list.len() <= 1
+                        || self.options.duplicates == Duplicates::Last;
+
+                    if let Some(item) = value.list.last_mut() {
+                        push_line(item, text);
+                    }
+                    if current { push_line(&mut value.val, text); }
+                }
+                return;
+            }
+        }
+
+        self.open = None;
+
+        match classify(line, self.options) {
+            Line::Blank | Line::Comment => {},
+            Line::Group(name) => {
+                let (name, parent) = self.options.group_header(name);
+                self.group = self.options.group_name(name);
+
+                match parent {
+                    Some("") => self.error(
+                        "Expected a parent group name after ':'",
+                        line.trim()
+                    ),
+                    Some(parent) => {
+                        let parent = self.options.group_name(parent);
+                        self.parents.insert(self.group.clone(), parent);
+                    },
+                    None => {},
+                }
+            },
+            Line::Assignment(var, val) => {
+                let key = (self.group.clone(), self.options.fold(var));
+
+                let (val, quoted) = match parse_value(val, self.options) {
+                    Ok(v) => v,
+                    Err(msg) => return self.error(msg, line.trim()),
+                };
+
+                let origin = Origin::File {
+                    path: self.file.clone(),
+                    line: self.line,
+                };
+
+                // Quoted values cannot be continued.
+                if ! quoted {
+                    self.open = Some((Some(key.clone()), indent(line)));
+                }
+
+                match (self.values.get_mut(&key), self.options.duplicates) {
+                    (None, _) => {
+                        self.values.insert(key, Value::new(val, origin));
+                    },
+                    (Some(_), Duplicates::Error) => {
+                        // Skip its continuation lines too.
+                        self.open = Some((None, indent(line)));
+                        self.error("Duplicate variable", line.trim());
+                    },
+                    (Some(value), duplicates) => value.repeat(
+                        val, origin, duplicates == Duplicates::Last
+                    ),
+                }
+            },
+            Line::Invalid(msg) => self.error(msg, line.trim()),
+        }
+    }
+
+    /// Record an error that occurred while reading the file.
+    pub(super) fn read_error(&mut self, err: io::Error) {
+        self.errors.push(FileError::IO((self.file.clone(), err.kind())));
+    }
+
+    /// Finish parsing, returning the [Config] or the list of errors
+    /// encountered.
+    pub(super) fn finish(self) -> Result<Config, Vec<FileError>> {
+        if self.errors.is_empty() {
+            Ok(Config {
+                values: self.values,
+                parents: self.parents,
+                options: self.options.clone(),
+            })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn error(&mut self, msg: &str, data: &str) {
+        self.errors.push(FileError::Parse {
+            file: self.file.clone(),
+            msg: msg.into(),
+            data: data.into(),
+            line: self.line,
+        });
+    }
+}
+
+/// Append a continuation line to a value.
+fn push_line(value: &mut String, text: &str) {
+    // A value may begin on the line after its variable name.
+    if ! value.is_empty() { value.push('\n'); }
+    value.push_str(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn parse(text: &str, options: &ParserOptions)
+    -> Result<Config, Vec<FileError>> {
+        let mut parser = Parser::new(Path::new("<test>"), options);
+        for line in text.lines() { parser.parse_line(line); }
+        parser.finish()
+    }
+
+    #[test]
+    fn comments_and_separators() {
+        let options = ParserOptions::new()
+            .comment_prefixes(&["#", "//"])
+            .inline_comment_prefixes(&[";", "#"])
+            .separators(&[':', '=']);
+
+        let conf = parse("\
+# comment
+// comment
+; not a comment: value ; comment
+[Group] # comment
+a: b = c
+url = http://example.com/#anchor # comment
+quoted = \"x # y\" # comment
+long = first # comment
+    second ; comment
+", &options).unwrap();
+
+        assert_eq!(conf["; not a comment"], "value");
+        assert_eq!(conf[("Group", "a")], "b = c");
+        assert_eq!(conf[("Group", "url")], "http://example.com/#anchor");
+        assert_eq!(conf[("Group", "quoted")], "x # y");
+        assert_eq!(conf[("Group", "long")], "first\nsecond");
+
+        let conf = parse("\
+a = 1
+    # note
+    2
+  // note
+b = 3
+    # not a value
+", &options).unwrap();
+
+        assert_eq!(conf["a"], "1\n2");
+        assert_eq!(conf["b"], "3");
+
+        let errs = parse("a = \"x\" y # z\n", &options).unwrap_err();
+        assert!(matches!(
+            errs[0],
+            FileError::Parse { ref msg, .. }
+                if msg == "Unexpected text after closing quote"
+        ));
+    }
+
+    #[test]
+    fn case_folding_and_default_group() {
+        let options = ParserOptions::new()
+            .case_sensitive(false)
+            .default_group("Global");
+
+        let conf = parse("\
+A = 1
+[GLOBAL]
+B = 2
+[Server]
+Port = 80
+", &options).unwrap();
+
+        assert_eq!(conf["a"], "1");
+        assert_eq!(conf["b"], "2");
+        assert_eq!(conf[("server", "port")], "80");
+        assert_eq!(conf[("SERVER", "PORT")], "80");
+        assert_eq!(
+            conf.groups().collect::<Vec<_>>(),
+            vec!["DEFAULT", "server"]
+        );
+    }
+
+    #[test]
+    fn disable_continuation_lines() {
+        let text = "x = 1\n    y = 2\n    ; comment\n  [Group]\n  z = 3\n";
+
+        let conf = parse(text, &ParserOptions::new()).unwrap();
+        assert_eq!(conf["x"], "1\ny = 2\n[Group]\nz = 3");
+
+        let options = ParserOptions::new().continuation_lines(false);
+        let conf = parse(text, &options).unwrap();
+        assert_eq!(conf["x"], "1");
+        assert_eq!(conf["y"], "2");
+        assert_eq!(conf[("Group", "z")], "3");
+
+        let conf = conf.set("Group", "lines", "a\nb");
+        assert_eq!(
+            conf.to_string(),
+            "[DEFAULT]\nx = 1\ny = 2\n[Group]\nz = 3\nlines = \"a\\nb\"\n"
+        );
+        assert_eq!(
+            parse(&conf.to_string(), &options).unwrap()[("Group", "lines")],
+            "a\nb"
+        );
+    }
+
+    #[test]
+    fn duplicate_policies() {
+        let text = "a = 1\n    more\nb = 2\na = 3\n    ignored\n";
+
+        let conf = parse(text, &ParserOptions::new()).unwrap();
+        assert_eq!(conf["a"], "3\nignored");
+
+        let options = ParserOptions::new().duplicates(Duplicates::First);
+        let conf = parse(text, &options).unwrap();
+        assert_eq!(conf["a"], "1\nmore");
+        assert_eq!(conf["b"], "2");
+
+        let options = ParserOptions::new().duplicates(Duplicates::Error);
+        let errs = parse(text, &options).unwrap_err();
+        match &errs[..] {
+            [FileError::Parse { msg, line: 4, .. }] =>
+                assert_eq!(msg, "Duplicate variable"),
+            _ => panic!("Expected a duplicate variable error; got {:?}", errs),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Environment-specific profiles
+//!
+//! A configuration can hold variations of a group for different environments
+//! (profiles) alongside the group itself:
+//!
+//! ```ini
+//! [database]
+//! host = localhost
+//! pool = 4
+//!
+//! [database:production]
+//! host = db.example.com
+//! ```
+//!
+//! [Config::with_profile] selects a profile by overlaying the variables of
+//! each profile group on its base group; the [Origin] of an overlaid value is
+//! an [Origin::Profile] naming the profile group it came from. The profile
+//! groups themselves are left unchanged.
+//!
+//! The name of a profile group is `{group}:{profile}` by default;
+//! [Config::with_profile_syntax] accepts other patterns. Because
+//! [ParserOptions::inheritance](super::ParserOptions::inheritance) also uses
+//! ':' in group headers, a different pattern is needed when inheritance is
+//! enabled.
+//!
+//! # Example
+//!
+//! ```
+//! # use utility_belt::config::{Config, Origin};
+//! let conf: Config = "\
+//! [database]
+//! host = localhost
+//! pool = 4
+//! [database:production]
+//! host = db.example.com
+//! ".parse().unwrap();
+//!
+//! let conf = conf.with_profile("production");
+//!
+//! assert_eq!(conf[("database", "host")], "db.example.com");
+//! assert_eq!(conf[("database", "pool")], "4");
+//! assert!(matches!(
+//!     conf.origin("database", "host"),
+//!     Some(Origin::Profile { group, .. }) if group == "database:production"
+//! ));
+//! ```
+
+use super::{Config, Origin};
+
+
+/// The default pattern for the names of profile groups.
+const DEFAULT_SYNTAX: &str = "{group}:{profile}";
+
+impl Config {
+    /// Apply the named profile, overlaying each `[group:profile]` group on
+    /// `[group]`.
+    ///
+    /// See the [profile module](self) documentation.
+    pub fn with_profile(self, profile: &str) -> Self {
+        self.with_profile_syntax(profile, DEFAULT_SYNTAX)
+    }
+
+    /// Apply the named profile, with profile group names given by `syntax`.
+    ///
+    /// `syntax` is a pattern in which `{group}` stands for the name of the base
+    /// group and `{profile}` for the profile's name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `syntax` does not contain `{group}`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use utility_belt::config::Config;
+    /// let conf: Config = "[db]\nhost = a\n[staging.db]\nhost = b\n"
+    ///     .parse()
+    ///     .unwrap();
+    ///
+    /// let conf = conf.with_profile_syntax("staging", "{profile}.{group}");
+    /// assert_eq!(conf[("db", "host")], "b");
+    /// ```
+    pub fn with_profile_syntax(mut self, profile: &str, syntax: &str) -> Self {
+        let syntax = syntax.replace("{profile}", &self.options.fold(profile));
+        let (prefix, suffix) = syntax.split_once("{group}")
+            .expect("Profile syntax must contain {group}");
+
+        let overlays: Vec<_> = self.values.iter()
+            .filter_map(|((group, var), value)| {
+                let base = group.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                if base.is_empty() { return None; }
+
+                let mut value = value.clone();
+                value.origin = Origin::Profile {
+                    group: group.clone(),
+                    origin: Box::new(value.origin),
+                };
+
+                Some(((base.to_string(), var.clone()), value))
+            })
+            .collect();
+
+        for (key, value) in overlays {
+            self.values.insert(key, value);
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ParserOptions;
+
+
+    #[test]
+    fn overlay_profile_groups() {
+        let conf: Config = "\
+x = 1
+[A]
+x = 1
+y = 1
+    more
+[A:prod]
+y = 2
+z = 2
+[A:dev]
+y = 3
+[B:prod]
+x = 2
+[DEFAULT:prod]
+x = 2
+".parse().unwrap();
+
+        let prod = conf.clone().with_profile("prod");
+
+        assert_eq!(prod[("A", "x")], "1");
+        assert_eq!(prod[("A", "y")], "2");
+        assert_eq!(prod[("A", "z")], "2");
+        assert_eq!(prod[("B", "x")], "2");
+        assert_eq!(prod["x"], "2");
+        assert_eq!(prod[("A:dev", "y")], "3");
+        assert_eq!(prod.origin("A", "x"), conf.origin("A", "x"));
+        assert_eq!(prod.origin("A", "y"), Some(&Origin::Profile {
+            group: "A:prod".into(),
+            origin: Box::new(Origin::File {
+                path: "<string>".into(),
+                line: 7,
+            }),
+        }));
+        assert_eq!(
+            prod.origin("A", "y").unwrap().to_string(),
+            "line 7 in <string> (profile group A:prod)"
+        );
+
+        let vars: Vec<_> = prod.variables_in_group("A").collect();
+        assert_eq!(vars, vec!["x", "y", "z"]);
+
+        let none = conf.clone().with_profile("test");
+        assert_eq!(none.to_string(), conf.to_string());
+    }
+
+    #[test]
+    fn custom_profile_syntax() {
+        let options = ParserOptions::new()
+            .case_sensitive(false)
+            .inheritance(true);
+        let conf = Config::from_reader_with(
+            "[Base]\nx = 1\n[Child : Base]\n[Child@Prod]\nX = 2\n".as_bytes(),
+            "<string>",
+            &options
+        ).unwrap();
+
+        let conf = conf.with_profile_syntax("PROD", "{group}@{profile}");
+
+        assert_eq!(conf[("child", "x")], "2");
+        assert_eq!(conf[("base", "x")], "1");
+    }
+
+    #[test]
+    #[should_panic(expected = "Profile syntax must contain {group}")]
+    fn profile_syntax_needs_group() {
+        Config::default().with_profile_syntax("prod", "{profile}");
+    }
+}
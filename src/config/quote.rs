@@ -0,0 +1,168 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Quoted values
+//!
+//! A value beginning with a double quote extends to the next unescaped double
+//! quote, and may contain the escape sequences `\n`, `\t`, `\"`, `\\`, and
+//! `\u{XXXX}` (a Unicode scalar value in hexadecimal).
+
+use std::borrow::Cow;
+
+use super::ParserOptions;
+
+
+/// Parse the quoted value `val`, which must begin with a double quote.
+///
+/// Returns the unquoted value and the text following the closing quote.
+pub(super) fn unquote(val: &str) -> Result<(String, &str), &'static str> {
+    let mut chars = val.strip_prefix('"')
+        .expect("Quoted values must begin with a quote")
+        .chars();
+    let mut unquoted = String::with_capacity(val.len());
+
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => unquoted.push(unescape(&mut chars)?),
+            Some(c) => unquoted.push(c),
+            None => return Err("Missing closing quote"),
+        }
+    }
+
+    Ok((unquoted, chars.as_str()))
+}
+
+/// Parse an escape sequence; the backslash has already been consumed.
+fn unescape(chars: &mut std::str::Chars) -> Result<char, &'static str> {
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('"') => Ok('"'),
+        Some('\\') => Ok('\\'),
+        Some('u') => {
+            let rest = chars.as_str().strip_prefix('{')
+                .ok_or("Expected '{' after \\u")?;
+            let end = rest.find('}').ok_or("Missing '}' in \\u{...}")?;
+
+            let c = u32::from_str_radix(&rest[..end], 16).ok()
+                .filter(|_| (1..=6).contains(&end))
+                .and_then(char::from_u32)
+                .ok_or("Invalid Unicode scalar value in \\u{...}")?;
+
+            *chars = rest[end+1..].chars();
+            Ok(c)
+        },
+        Some(_) => Err("Invalid escape sequence"),
+        None => Err("Missing closing quote"),
+    }
+}
+
+/// Determine whether `val` must be quoted to be read back unchanged.
+///
+/// Multi-line values that can be written as continuation lines are not
+/// quoted unless the options disable continuation lines.
+fn needs_quotes(val: &str, options: &ParserOptions) -> bool {
+    let multi_line = val.contains('\n');
+
+    val.starts_with('"')
+        || (multi_line && ! options.continuation_lines)
+        || val.split('\n').any(|line| options.has_comment(line))
+        || val.split('\n').any(|line|
+            line != line.trim() || (multi_line && line.is_empty())
+        )
+        || val.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
+}
+
+/// Format `val` so that it is read back unchanged, quoting and escaping it if
+/// necessary.
+pub(super) fn quote<'a>(val: &'a str, options: &ParserOptions)
+-> Cow<'a, str> {
+    if needs_quotes(val, options) {
+        Cow::Owned(quote_always(val))
+    } else {
+        Cow::Borrowed(val)
+    }
+}
+
+/// Quote and escape `val`, whether or not it needs to be.
+pub(super) fn quote_always(val: &str) -> String {
+    let mut quoted = String::with_capacity(val.len() + 2);
+    quoted.push('"');
+
+    for c in val.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() =>
+                quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn unquote_escapes() {
+        assert_eq!(
+            unquote(r#""  a\tb\n\"c\" \\ \u{e9}\u{1F600} ; x "  "#).unwrap(),
+            ("  a\tb\n\"c\" \\ \u{e9}\u{1F600} ; x ".into(), "  ")
+        );
+        assert_eq!(unquote(r#""" rest"#).unwrap(), ("".into(), " rest"));
+    }
+
+    #[test]
+    fn invalid_quoted_values_are_err() {
+        assert_eq!(unquote(r#""abc"#), Err("Missing closing quote"));
+        assert_eq!(unquote(r#""a\"#), Err("Missing closing quote"));
+        assert_eq!(unquote(r#""\x""#), Err("Invalid escape sequence"));
+        assert!(unquote(r#""\u{110000}""#).is_err());
+        assert!(unquote(r#""\u{}""#).is_err());
+        assert!(unquote(r#""\u41""#).is_err());
+    }
+
+    #[test]
+    fn quote_only_when_needed() {
+        let options = ParserOptions::default();
+
+        assert_eq!(quote("plain value", &options), "plain value");
+        assert_eq!(quote("say \"hi\"", &options), "say \"hi\"");
+        assert_eq!(quote("one\ntwo", &options), "one\ntwo");
+        assert_eq!(quote("", &options), "");
+
+        assert_eq!(quote(" padded ", &options), "\" padded \"");
+        assert_eq!(quote("; not a comment", &options), "\"; not a comment\"");
+        assert_eq!(quote("\"quoted\"", &options), r#""\"quoted\"""#);
+        assert_eq!(quote("one\n\n  two", &options), r#""one\n\n  two""#);
+        assert_eq!(quote("\nabc", &options), r#""\nabc""#);
+        assert_eq!(quote("a\rb\\", &options), r#""a\u{d}b\\""#);
+
+        let options = ParserOptions::new()
+            .comment_prefixes(&["#"])
+            .inline_comment_prefixes(&["//"]);
+
+        assert_eq!(quote("; comment", &options), "; comment");
+        assert_eq!(quote("# comment", &options), "\"# comment\"");
+        assert_eq!(quote("a\n# b", &options), r#""a\n# b""#);
+        assert_eq!(quote("http://x", &options), "\"http://x\"");
+    }
+
+    #[test]
+    fn quoted_values_round_trip() {
+        let options = ParserOptions::default();
+
+        for val in &[" a ", "\"", "x\n\ny", "\t", "a\u{7}b\\"] {
+            assert_eq!(&unquote(&quote(val, &options)).unwrap().0, val);
+        }
+    }
+}
@@ -0,0 +1,639 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Validate a configuration against a schema
+//!
+//! A [Schema] describes the groups and variables a configuration is expected
+//! to contain, along with each variable's type, default value, and
+//! constraints. [Schema::validate] checks a [Config] against the schema and
+//! returns every [Violation] it finds.
+//!
+//! Variables that are not described by the schema are reported as warnings,
+//! with the closest described variable as a suggestion, so that a typo such as
+//! `prot = 8080` does not silently leave `port` at its default. Use
+//! [Schema::strict] to report them as errors instead, or
+//! [GroupSchema::allow_unknown] to accept any variable in a group.
+//!
+//! # Example
+//!
+//! ```
+//! # use utility_belt::config::{
+//! #     Config,
+//! #     schema::{GroupSchema, Schema, Severity, ValueType, VarSchema},
+//! # };
+//! let schema = Schema::new()
+//!     .group(GroupSchema::new("Server")
+//!         .var("port", VarSchema::new(ValueType::Integer)
+//!             .required()
+//!             .min(1.0)
+//!             .max(65535.0))
+//!         .var("mode", VarSchema::new(ValueType::String)
+//!             .allowed(&["development", "production"])
+//!             .default("production")));
+//!
+//! let conf: Config = "[Server]\nport = 8080\nmdoe = test\n".parse().unwrap();
+//!
+//! let violations = schema.validate(&conf);
+//! assert_eq!(violations.len(), 1);
+//! assert_eq!(violations[0].severity, Severity::Warning);
+//! assert_eq!(
+//!     violations[0].to_string(),
+//!     "[Server] mdoe: Unknown variable (did you mean 'mode'?) \
+//!         (line 3 in <string>)"
+//! );
+//!
+//! let conf = schema.apply_defaults(conf);
+//! assert_eq!(conf[("Server", "mode")], "production");
+//! ```
+
+use std::fmt;
+
+use regex::Regex;
+
+use super::{Config, Origin};
+
+
+/// The type of a configuration value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// Any value.
+    String,
+    /// A value that parses as an `i64`.
+    Integer,
+    /// A value that parses as an `f64`.
+    Float,
+    /// A value that parses as a `bool` (`true` or `false`).
+    Bool,
+}
+
+impl ValueType {
+    /// Parse `val` as this type, returning its numeric value if it has one.
+    fn parse(self, val: &str) -> Result<Option<f64>, ()> {
+        let valid = match self {
+            ValueType::String => true,
+            ValueType::Integer => {
+                return val.parse::<i64>().map(|v| Some(v as f64))
+                    .map_err(|_| ());
+            },
+            ValueType::Float => return val.parse().map(Some).map_err(|_| ()),
+            ValueType::Bool => val.parse::<bool>().is_ok(),
+        };
+
+        if valid { Ok(None) } else { Err(()) }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueType::String => write!(f, "string"),
+            ValueType::Integer => write!(f, "integer"),
+            ValueType::Float => write!(f, "float"),
+            ValueType::Bool => write!(f, "boolean"),
+        }
+    }
+}
+
+/// The expected type and constraints of a variable.
+#[derive(Debug, Clone)]
+pub struct VarSchema {
+    ty: ValueType,
+    required: bool,
+    default: Option<String>,
+    allowed: Vec<String>,
+    min: Option<f64>,
+    max: Option<f64>,
+    // The pattern as given, and as compiled to match the entire value.
+    pattern: Option<(String, Regex)>,
+}
+
+impl VarSchema {
+    /// Describe an optional variable of the specified type.
+    pub fn new(ty: ValueType) -> Self {
+        Self {
+            ty,
+            required: false,
+            default: None,
+            allowed: vec![],
+            min: None,
+            max: None,
+            pattern: None,
+        }
+    }
+
+    /// Require the variable to be set, unless it has a default value.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Set the value used by [Schema::apply_defaults] if the variable is not
+    /// set.
+    pub fn default(mut self, val: &str) -> Self {
+        self.default = Some(val.into());
+        self
+    }
+
+    /// Restrict the variable to the listed values.
+    pub fn allowed(mut self, values: &[&str]) -> Self {
+        self.allowed = values.iter().map(|v| v.to_string()).collect();
+        self
+    }
+
+    /// Set the minimum (inclusive) of a numeric variable.
+    pub fn min(mut self, min: f64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    /// Set the maximum (inclusive) of a numeric variable.
+    pub fn max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Require the entire value to match the regular expression `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is not a valid regular expression.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        let anchored = format!("^(?:{})$", pattern);
+        let re = Regex::new(&anchored).expect("Invalid regular expression");
+
+        self.pattern = Some((pattern.into(), re));
+        self
+    }
+
+    /// Check the value `val`, returning the constraint it violates, if any.
+    fn check(&self, val: &str) -> Option<ViolationKind> {
+        let num = match self.ty.parse(val) {
+            Ok(num) => num,
+            Err(()) => return Some(ViolationKind::Type(self.ty)),
+        };
+
+        if let Some(num) = num {
+            if self.min.is_some_and(|min| num < min)
+                || self.max.is_some_and(|max| num > max)
+            {
+                return Some(ViolationKind::Range {
+                    min: self.min,
+                    max: self.max,
+                });
+            }
+        }
+
+        if ! self.allowed.is_empty() && ! self.allowed.iter().any(|a| a == val)
+        {
+            return Some(ViolationKind::NotAllowed(self.allowed.clone()));
+        }
+
+        match self.pattern {
+            Some((ref pattern, ref re)) if ! re.is_match(val) =>
+                Some(ViolationKind::Pattern(pattern.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// The variables expected in a group.
+#[derive(Debug, Clone)]
+pub struct GroupSchema {
+    name: String,
+    vars: Vec<(String, VarSchema)>,
+    allow_unknown: bool,
+}
+
+impl GroupSchema {
+    /// Describe the group called `name`.
+    pub fn new(name: &str) -> Self {
+        Self { name: name.into(), vars: vec![], allow_unknown: false }
+    }
+
+    /// Describe a variable in the group.
+    pub fn var(mut self, name: &str, var: VarSchema) -> Self {
+        self.vars.push((name.into(), var));
+        self
+    }
+
+    /// If `true`, variables not described by the schema are not reported.
+    pub fn allow_unknown(mut self, allow: bool) -> Self {
+        self.allow_unknown = allow;
+        self
+    }
+
+    /// Determine whether the group describes the variable named `var` in
+    /// `conf`, comparing names as `conf` does.
+    fn describes(&self, conf: &Config, var: &str) -> bool {
+        self.vars.iter().any(|(name, _)| conf.options.fold(name) == var)
+    }
+}
+
+/// The expected shape of a configuration.
+///
+/// See the [schema module](super::schema) documentation for an example.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    groups: Vec<GroupSchema>,
+    strict: bool,
+}
+
+impl Schema {
+    /// Create an empty schema.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Describe a group.
+    pub fn group(mut self, group: GroupSchema) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    /// If `true`, groups and variables not described by the schema are
+    /// reported as errors rather than warnings.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Check `conf` against the schema.
+    ///
+    /// # Returns
+    ///
+    /// Returns every violation found; the configuration is valid if none of
+    /// them is an [Severity::Error].
+    pub fn validate(&self, conf: &Config) -> Vec<Violation> {
+        let mut violations = vec![];
+
+        for group in &self.groups {
+            for (name, var) in &group.vars {
+                let value = conf.value(&group.name, name);
+
+                let kind = match value {
+                    Some(v) => var.check(&v.val),
+                    None if var.required && var.default.is_none() =>
+                        Some(ViolationKind::Missing),
+                    None => None,
+                };
+
+                if let Some(kind) = kind {
+                    violations.push(Violation {
+                        group: group.name.clone(),
+                        variable: name.clone(),
+                        value: value.map(|v| v.val.clone()),
+                        severity: Severity::Error,
+                        kind,
+                        origin: value.map(|v| v.origin.clone()),
+                    });
+                }
+            }
+        }
+
+        let severity = if self.strict {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+
+        for ((group, var), value) in conf.values.iter() {
+            let schema = self.groups.iter()
+                .find(|g| &conf.options.group_name(&g.name) == group);
+
+            let kind = match schema {
+                Some(g) if g.allow_unknown || g.describes(conf, var) =>
+                    continue,
+                Some(g) => ViolationKind::Unknown {
+                    suggestion: closest(var, g.vars.iter().map(|(n, _)| n)),
+                },
+                None => ViolationKind::UnknownGroup {
+                    suggestion: closest(
+                        group,
+                        self.groups.iter().map(|g| &g.name)
+                    ),
+                },
+            };
+
+            violations.push(Violation {
+                group: group.clone(),
+                variable: var.clone(),
+                value: Some(value.val.clone()),
+                severity,
+                kind,
+                origin: Some(value.origin.clone()),
+            });
+        }
+
+        violations
+    }
+
+    /// Set the default value of every variable in the schema that is not set
+    /// in `conf`.
+    pub fn apply_defaults(&self, mut conf: Config) -> Config {
+        for group in &self.groups {
+            for (name, var) in &group.vars {
+                if let Some(ref default) = var.default {
+                    if conf.get(&group.name, name).is_none() {
+                        conf = conf.set(&group.name, name, default);
+                    }
+                }
+            }
+        }
+
+        conf
+    }
+}
+
+/// The severity of a [Violation].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The configuration is probably, but not necessarily, incorrect.
+    Warning,
+    /// The configuration is invalid.
+    Error,
+}
+
+/// The way in which a value violates the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+    /// A required variable is not set.
+    Missing,
+    /// The value cannot be parsed as the expected type.
+    Type(ValueType),
+    /// The value is outside the allowed range.
+    Range { min: Option<f64>, max: Option<f64> },
+    /// The value is not one of the listed values.
+    NotAllowed(Vec<String>),
+    /// The value does not match the regular expression.
+    Pattern(String),
+    /// The variable is not described by the schema of its group.
+    /// `suggestion` names the most similar variable, if any is close enough to
+    /// be a likely typo.
+    Unknown { suggestion: Option<String> },
+    /// The variable's group is not described by the schema. `suggestion`
+    /// names the most similar group, if any is close enough to be a likely
+    /// typo.
+    UnknownGroup { suggestion: Option<String> },
+}
+
+/// A way in which a configuration does not match a [Schema].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The group containing the variable.
+    pub group: String,
+    /// The name of the variable.
+    pub variable: String,
+    /// The variable's value, if it is set.
+    pub value: Option<String>,
+    /// Whether the violation is an error or a warning.
+    pub severity: Severity,
+    /// The constraint that was violated.
+    pub kind: ViolationKind,
+    /// Where the value was set, if it is set.
+    pub origin: Option<Origin>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}: ", self.group, self.variable)?;
+
+        let value = self.value.as_deref().unwrap_or_default();
+
+        match self.kind {
+            ViolationKind::Missing =>
+                write!(f, "Required variable is not set")?,
+            ViolationKind::Type(ty) =>
+                write!(f, "Cannot parse {:?} as {}", value, ty)?,
+            ViolationKind::Range { min, max } => {
+                write!(f, "{} is out of range (", value)?;
+                match (min, max) {
+                    (Some(min), Some(max)) => write!(f, "{} to {}", min, max)?,
+                    (Some(min), None) => write!(f, "at least {}", min)?,
+                    (None, Some(max)) => write!(f, "at most {}", max)?,
+                    (None, None) => write!(f, "unbounded")?,
+                }
+                write!(f, ")")?;
+            },
+            ViolationKind::NotAllowed(ref allowed) =>
+                write!(f, "{:?} is not one of {:?}", value, allowed)?,
+            ViolationKind::Pattern(ref pattern) =>
+                write!(f, "{:?} does not match /{}/", value, pattern)?,
+            ViolationKind::Unknown { ref suggestion } => {
+                write!(f, "Unknown variable")?;
+                if let Some(s) = suggestion {
+                    write!(f, " (did you mean '{}'?)", s)?;
+                }
+            },
+            ViolationKind::UnknownGroup { ref suggestion } => {
+                write!(f, "Unknown group")?;
+                if let Some(s) = suggestion {
+                    write!(f, " (did you mean '[{}]'?)", s)?;
+                }
+            },
+        }
+
+        if let Some(ref origin) = self.origin {
+            write!(f, " ({})", origin)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// Find the name in `names` most similar to `name`, if any is similar enough
+/// to suggest that `name` is a misspelling of it.
+fn closest<'a>(name: &str, names: impl Iterator<Item = &'a String>)
+-> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    names.map(|n| (edit_distance(name, n), n))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n.clone())
+}
+
+/// The number of insertions, deletions, substitutions, and transpositions of
+/// adjacent characters needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<_> = a.chars().collect();
+    let b: Vec<_> = b.chars().collect();
+
+    // rows[i][j] is the distance between a[..i] and b[..j].
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() { row[0] = i; }
+    for (j, dist) in rows[0].iter_mut().enumerate() { *dist = j; }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i-1] == b[j-1] { 0 } else { 1 };
+
+            let mut dist = (rows[i-1][j] + 1)
+                .min(rows[i][j-1] + 1)
+                .min(rows[i-1][j-1] + cost);
+
+            if i > 1 && j > 1 && a[i-1] == b[j-2] && a[i-2] == b[j-1] {
+                dist = dist.min(rows[i-2][j-2] + 1);
+            }
+
+            rows[i][j] = dist;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ParserOptions;
+
+
+    fn schema() -> Schema {
+        Schema::new()
+            .group(GroupSchema::new("DEFAULT")
+                .var("name", VarSchema::new(ValueType::String)
+                    .pattern("[a-z]+"))
+                .var("debug", VarSchema::new(ValueType::Bool)
+                    .default("false")))
+            .group(GroupSchema::new("Server")
+                .var("port", VarSchema::new(ValueType::Integer)
+                    .required()
+                    .min(1.0)
+                    .max(65535.0))
+                .var("ratio", VarSchema::new(ValueType::Float).min(0.0))
+                .var("mode", VarSchema::new(ValueType::String)
+                    .allowed(&["dev", "prod"])))
+            .group(GroupSchema::new("Extra").allow_unknown(true))
+    }
+
+    fn kinds(violations: &[Violation]) -> Vec<(&str, &ViolationKind)> {
+        violations.iter().map(|v| (v.variable.as_str(), &v.kind)).collect()
+    }
+
+    #[test]
+    fn valid_config_has_no_violations() {
+        let conf = Config::default()
+            .set_default("name", "app")
+            .set("Server", "port", "8080")
+            .set("Server", "ratio", "0.5")
+            .set("Server", "mode", "dev")
+            .set("Extra", "anything", "goes");
+
+        assert!(schema().validate(&conf).is_empty());
+    }
+
+    #[test]
+    fn report_every_violation() {
+        let conf = Config::default()
+            .set_default("name", "App1")
+            .set_default("debug", "yes")
+            .set("Server", "ratio", "-1")
+            .set("Server", "mode", "test");
+
+        let violations = schema().validate(&conf);
+
+        assert_eq!(kinds(&violations), vec![
+            ("name", &ViolationKind::Pattern("[a-z]+".into())),
+            ("debug", &ViolationKind::Type(ValueType::Bool)),
+            ("port", &ViolationKind::Missing),
+            ("ratio", &ViolationKind::Range { min: Some(0.0), max: None }),
+            ("mode", &ViolationKind::NotAllowed(vec!["dev".into(),
+                "prod".into()])),
+        ]);
+        assert!(violations.iter().all(|v| v.severity == Severity::Error));
+
+        assert_eq!(
+            violations[3].to_string(),
+            "[Server] ratio: -1 is out of range (at least 0) \
+                (set programmatically)"
+        );
+    }
+
+    #[test]
+    fn port_out_of_range() {
+        let conf = Config::default().set("Server", "port", "70000");
+
+        assert_eq!(kinds(&schema().validate(&conf)), vec![
+            ("port", &ViolationKind::Range {
+                min: Some(1.0),
+                max: Some(65535.0),
+            }),
+        ]);
+    }
+
+    #[test]
+    fn unknown_keys_are_warnings() {
+        let conf = Config::default()
+            .set("Server", "port", "80")
+            .set("Server", "prot", "8080")
+            .set("Sever", "mode", "dev")
+            .set("Other", "x", "y");
+
+        let violations = schema().validate(&conf);
+
+        assert_eq!(kinds(&violations), vec![
+            ("prot", &ViolationKind::Unknown {
+                suggestion: Some("port".into())
+            }),
+            ("mode", &ViolationKind::UnknownGroup {
+                suggestion: Some("Server".into())
+            }),
+            ("x", &ViolationKind::UnknownGroup { suggestion: None }),
+        ]);
+        assert_eq!(
+            violations[1].to_string(),
+            "[Sever] mode: Unknown group (did you mean '[Server]'?) \
+                (set programmatically)"
+        );
+        assert!(violations.iter().all(|v| v.severity == Severity::Warning));
+
+        let violations = schema().strict(true).validate(&conf);
+        assert!(violations.iter().all(|v| v.severity == Severity::Error));
+    }
+
+    #[test]
+    fn compare_names_as_the_config_does() {
+        let options = ParserOptions::new()
+            .case_sensitive(false)
+            .default_group("general");
+        let conf = Config::from_reader_with(
+            "[General]\nName = app\n[SERVER]\nPort = 80\nProt = 8\n"
+                .as_bytes(),
+            "<string>",
+            &options
+        ).unwrap();
+
+        let schema = Schema::new()
+            .group(GroupSchema::new("general")
+                .var("name", VarSchema::new(ValueType::String)))
+            .group(GroupSchema::new("Server")
+                .var("port", VarSchema::new(ValueType::Integer).required()));
+
+        assert_eq!(kinds(&schema.validate(&conf)), vec![
+            ("prot", &ViolationKind::Unknown {
+                suggestion: Some("port".into())
+            }),
+        ]);
+    }
+
+    #[test]
+    fn apply_defaults_to_unset_values() {
+        let conf = schema().apply_defaults(Config::default());
+        assert_eq!(conf["debug"], "false");
+
+        let conf = Config::default().set_default("debug", "true");
+        assert_eq!(schema().apply_defaults(conf)["debug"], "true");
+    }
+
+    #[test]
+    fn edit_distance_counts_transpositions() {
+        assert_eq!(edit_distance("port", "prot"), 1);
+        assert_eq!(edit_distance("port", "ports"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,684 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Serialize user types into a [Config]
+//!
+//! This is the counterpart to [from_config](super::from_config): scalar fields
+//! of the top-level struct are stored in the DEFAULT group, and fields that are
+//! themselves structs or maps become groups of the same name. `None` values
+//! are skipped.
+//!
+//! # Example
+//!
+//! ```
+//! # use serde::Serialize;
+//! # use utility_belt::config::to_config;
+//! #[derive(Serialize)]
+//! struct Settings {
+//!     name: String,
+//!     server: Server,
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct Server {
+//!     port: u16,
+//! }
+//!
+//! let settings = Settings {
+//!     name: "my app".into(),
+//!     server: Server { port: 8080 },
+//! };
+//!
+//! let conf = to_config(&settings).unwrap();
+//! assert_eq!(conf["name"], "my app");
+//! assert_eq!(conf[("server", "port")], "8080");
+//! ```
+
+use std::fmt;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use super::Config;
+
+
+/// Serialize a `T` into a new [Config].
+pub fn to_config<T>(value: &T) -> Result<Config, Error>
+    where T: Serialize + ?Sized,
+{
+    value.serialize(ConfigSerializer)
+}
+
+/// Serialize a `T` into INI text.
+///
+/// The text is identical to that written by [Config::write_to_file_blocking].
+pub fn to_string<T>(value: &T) -> Result<String, Error>
+    where T: Serialize + ?Sized,
+{
+    to_config(value).map(|c| c.to_string())
+}
+
+/// Error for values that cannot be serialized.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The value has a shape that cannot be represented in a [Config].
+    Unsupported(String),
+    /// Any other error reported while serializing.
+    Custom(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Unsupported(ref msg) => write!(f, "{}", msg),
+            Error::Custom(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+fn unsupported<T>(msg: &str) -> Result<T, Error> {
+    Err(Error::Unsupported(msg.into()))
+}
+
+/// The result of serializing a single field.
+enum Field {
+    /// A variable's value.
+    Value(String),
+    /// A complete group, as a list of variable/value pairs.
+    Group(Vec<(String, String)>),
+    /// A field with no value (e.g., `None`).
+    Skip,
+}
+
+/// Serializes the top-level value into a [Config].
+struct ConfigSerializer;
+
+const TOP_LEVEL: &str = "A Config can only be serialized from a struct or map";
+
+impl ser::Serializer for ConfigSerializer {
+    type Ok = Config;
+    type Error = Error;
+    type SerializeSeq = Impossible<Config, Error>;
+    type SerializeTuple = Impossible<Config, Error>;
+    type SerializeTupleStruct = Impossible<Config, Error>;
+    type SerializeTupleVariant = Impossible<Config, Error>;
+    type SerializeMap = ConfigBuilder;
+    type SerializeStruct = ConfigBuilder;
+    type SerializeStructVariant = Impossible<Config, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_none(self) -> Result<Config, Error> {
+        Ok(Config::default())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Config, Error>
+        where T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Config, Error> {
+        Ok(Config::default())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str)
+    -> Result<Config, Error> {
+        Ok(Config::default())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str
+    ) -> Result<Config, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T)
+    -> Result<Config, Error>
+        where T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T
+    ) -> Result<Config, Error>
+        where T: Serialize + ?Sized,
+    {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>)
+    -> Result<Self::SerializeSeq, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_tuple(self, _len: usize)
+    -> Result<Self::SerializeTuple, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize)
+    -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported(TOP_LEVEL)
+    }
+
+    fn serialize_map(self, _len: Option<usize>)
+    -> Result<Self::SerializeMap, Error> {
+        Ok(ConfigBuilder::default())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize)
+    -> Result<Self::SerializeStruct, Error> {
+        Ok(ConfigBuilder::default())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported(TOP_LEVEL)
+    }
+}
+
+/// Collects the fields of the top-level struct or map into a [Config].
+#[derive(Default)]
+struct ConfigBuilder {
+    conf: Config,
+    key: Option<String>,
+}
+
+impl ConfigBuilder {
+    fn add<T>(&mut self, name: &str, value: &T) -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        let conf = std::mem::take(&mut self.conf);
+
+        self.conf = match value.serialize(FieldSerializer { nested: false })? {
+            Field::Value(val) => conf.set_default(name, &val),
+            Field::Group(vars) => vars.iter()
+                .fold(conf, |c, (var, val)| c.set(name, var, val)),
+            Field::Skip => conf,
+        };
+
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for ConfigBuilder {
+    type Ok = Config;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T)
+    -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        self.add(key, value)
+    }
+
+    fn end(self) -> Result<Config, Error> {
+        Ok(self.conf)
+    }
+}
+
+impl ser::SerializeMap for ConfigBuilder {
+    type Ok = Config;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        self.key = Some(key_to_string(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        let key = self.key.take()
+            .ok_or_else(|| Error::Custom("Map value without a key".into()))?;
+        self.add(&key, value)
+    }
+
+    fn end(self) -> Result<Config, Error> {
+        Ok(self.conf)
+    }
+}
+
+/// Serialize a map key, which must be a scalar value.
+fn key_to_string<T>(key: &T) -> Result<String, Error>
+    where T: Serialize + ?Sized,
+{
+    match key.serialize(FieldSerializer { nested: true })? {
+        Field::Value(k) => Ok(k),
+        _ => unsupported("Map keys must be scalar values"),
+    }
+}
+
+/// Serializes the value of a single field.
+///
+/// Structs and maps are serialized into groups unless `nested` is set, since
+/// groups cannot contain other groups.
+struct FieldSerializer {
+    nested: bool,
+}
+
+const NESTED_SCALAR: &str = "Groups may only contain scalar values";
+
+impl ser::Serializer for FieldSerializer {
+    type Ok = Field;
+    type Error = Error;
+    type SerializeSeq = Impossible<Field, Error>;
+    type SerializeTuple = Impossible<Field, Error>;
+    type SerializeTupleStruct = Impossible<Field, Error>;
+    type SerializeTupleVariant = Impossible<Field, Error>;
+    type SerializeMap = GroupBuilder;
+    type SerializeStruct = GroupBuilder;
+    type SerializeStructVariant = Impossible<Field, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Field, Error> {
+        Ok(Field::Value(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Field, Error> {
+        Ok(Field::Value(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Field, Error> {
+        match std::str::from_utf8(v) {
+            Ok(s) => Ok(Field::Value(s.into())),
+            Err(_) => unsupported("Byte values must be valid UTF-8"),
+        }
+    }
+
+    fn serialize_none(self) -> Result<Field, Error> {
+        Ok(Field::Skip)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Field, Error>
+        where T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Field, Error> {
+        Ok(Field::Value(String::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str)
+    -> Result<Field, Error> {
+        Ok(Field::Value(String::new()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str
+    ) -> Result<Field, Error> {
+        Ok(Field::Value(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T)
+    -> Result<Field, Error>
+        where T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T
+    ) -> Result<Field, Error>
+        where T: Serialize + ?Sized,
+    {
+        unsupported("Only unit enum variants can be stored in a Config")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>)
+    -> Result<Self::SerializeSeq, Error> {
+        unsupported("Sequences cannot be stored in a Config")
+    }
+
+    fn serialize_tuple(self, _len: usize)
+    -> Result<Self::SerializeTuple, Error> {
+        unsupported("Tuples cannot be stored in a Config")
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize)
+    -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("Tuples cannot be stored in a Config")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("Only unit enum variants can be stored in a Config")
+    }
+
+    fn serialize_map(self, _len: Option<usize>)
+    -> Result<Self::SerializeMap, Error> {
+        if self.nested { return unsupported(NESTED_SCALAR); }
+        Ok(GroupBuilder::default())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize)
+    -> Result<Self::SerializeStruct, Error> {
+        if self.nested { return unsupported(NESTED_SCALAR); }
+        Ok(GroupBuilder::default())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("Only unit enum variants can be stored in a Config")
+    }
+}
+
+/// Collects the fields of a struct or map into a group.
+#[derive(Default)]
+struct GroupBuilder {
+    vars: Vec<(String, String)>,
+    key: Option<String>,
+}
+
+impl GroupBuilder {
+    fn add<T>(&mut self, name: String, value: &T) -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        match value.serialize(FieldSerializer { nested: true })? {
+            Field::Value(val) => self.vars.push((name, val)),
+            Field::Group(_) => return unsupported(NESTED_SCALAR),
+            Field::Skip => {},
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for GroupBuilder {
+    type Ok = Field;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T)
+    -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        self.add(key.into(), value)
+    }
+
+    fn end(self) -> Result<Field, Error> {
+        Ok(Field::Group(self.vars))
+    }
+}
+
+impl ser::SerializeMap for GroupBuilder {
+    type Ok = Field;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        self.key = Some(key_to_string(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize + ?Sized,
+    {
+        let key = self.key.take()
+            .ok_or_else(|| Error::Custom("Map value without a key".into()))?;
+        self.add(key, value)
+    }
+
+    fn end(self) -> Result<Field, Error> {
+        Ok(Field::Group(self.vars))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize};
+
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        name: String,
+        verbose: bool,
+        level: Level,
+        comment: Option<String>,
+        #[serde(rename = "Server Settings")]
+        server: Server,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Server {
+        port: u16,
+        ratio: f64,
+        timeout: Option<u32>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Level { Low, High }
+
+    fn settings() -> Settings {
+        Settings {
+            name: "app".into(),
+            verbose: true,
+            level: Level::Low,
+            comment: None,
+            server: Server { port: 8080, ratio: 0.25, timeout: Some(30) },
+        }
+    }
+
+    #[test]
+    fn serialize_struct_with_groups() {
+        let conf = to_config(&settings()).unwrap();
+
+        assert_eq!(conf["name"], "app");
+        assert_eq!(conf["verbose"], "true");
+        assert_eq!(conf["level"], "Low");
+        assert!(conf.get_default("comment").is_none());
+        assert_eq!(conf[("Server Settings", "port")], "8080");
+        assert_eq!(conf[("Server Settings", "ratio")], "0.25");
+        assert_eq!(conf[("Server Settings", "timeout")], "30");
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let conf = to_config(&settings()).unwrap();
+        let read: Settings = crate::config::from_config(&conf).unwrap();
+
+        assert_eq!(read, settings());
+    }
+
+    #[test]
+    fn serialize_to_ini_text() {
+        let text = to_string(&settings()).unwrap();
+
+        assert!(text.contains("[DEFAULT]\n"));
+        assert!(text.contains("name = app\n"));
+        assert!(text.contains("[Server Settings]\n"));
+        assert!(text.contains("port = 8080\n"));
+        assert!(! text.contains("comment"));
+    }
+
+    #[test]
+    fn serialize_maps() {
+        let mut group = BTreeMap::new();
+        group.insert("var2", "b");
+
+        let mut map = BTreeMap::new();
+        map.insert("Group", group);
+
+        let conf = to_config(&map).unwrap();
+        assert_eq!(conf[("Group", "var2")], "b");
+    }
+
+    #[test]
+    fn nested_groups_are_err() {
+        #[derive(Serialize)]
+        struct Outer { inner: Middle }
+
+        #[derive(Serialize)]
+        struct Middle { inner: Inner }
+
+        #[derive(Serialize)]
+        struct Inner { var: u8 }
+
+        let outer = Outer { inner: Middle { inner: Inner { var: 1 } } };
+        assert!(matches!(to_config(&outer), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn scalar_top_level_is_err() {
+        assert!(to_config(&5).is_err());
+        assert!(to_config("text").is_err());
+    }
+}
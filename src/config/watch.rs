@@ -0,0 +1,472 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reload configuration when its files change
+//!
+//! A [ConfigWatcher] loads a [Config] via a [ConfigLoader], then watches the
+//! directories containing every file the loader may read, along with any
+//! included files. When one of those files is created, modified, or removed,
+//! the loader is run again and each subscriber receives a [ConfigUpdate].
+//!
+//! If the new configuration cannot be loaded, the previous configuration is
+//! kept and subscribers receive the errors instead.
+//!
+//! The platform's native notification mechanism (e.g., inotify on Linux) is
+//! used when available; otherwise the directories are polled. The watched
+//! directories are updated after each successful reload, so a directory that
+//! does not exist when the watcher starts, or that contains a file included
+//! later, is watched from the first reload after it appears.
+
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, RwLock, Weak},
+    time::Duration,
+};
+
+use notify::{event::EventKind, Watcher};
+
+use super::{Config, ConfigDiff, ConfigLoader, FileError, Origin};
+
+
+/// The result of reloading the configuration, as delivered to subscribers.
+pub type WatchResult = Result<ConfigUpdate, Vec<FileError>>;
+
+/// A newly-loaded configuration.
+#[derive(Debug, Clone)]
+pub struct ConfigUpdate {
+    /// The new configuration.
+    pub config: Arc<Config>,
+    /// The variables that were added, removed, or changed.
+    pub changed: ConfigDiff,
+}
+
+/// Options that control how a [ConfigWatcher] detects changes.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    poll: bool,
+    poll_interval: Duration,
+    debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            poll: false,
+            poll_interval: Duration::from_secs(2),
+            debounce: Duration::from_millis(100),
+        }
+    }
+}
+
+impl WatchOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// If `true`, poll for changes even if native notifications are
+    /// available; this may be necessary for network filesystems.
+    pub fn poll(mut self, poll: bool) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Set how often to poll for changes. The default is two seconds.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set how long to wait for further changes before reloading, so that a
+    /// file written in several steps is only read once. The default is 100
+    /// milliseconds.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+}
+
+/// The watcher for the directories containing the configuration files.
+type DirWatcher = Mutex<Box<dyn Watcher + Send>>;
+
+/// State shared with the thread that reloads the configuration.
+#[derive(Debug)]
+struct Shared {
+    config: RwLock<Arc<Config>>,
+    subscribers: Mutex<Vec<mpsc::Sender<WatchResult>>>,
+}
+
+/// Watches the files read by a [ConfigLoader], reloading the configuration
+/// when they change.
+///
+/// Watching stops when the `ConfigWatcher` is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// # use utility_belt::config::{ConfigLoader, watch::ConfigWatcher};
+/// let loader = ConfigLoader::new("myapp").system().user();
+/// let watcher = ConfigWatcher::new(loader).unwrap();
+/// let updates = watcher.subscribe();
+///
+/// for update in updates {
+///     match update {
+///         Ok(update) => print!("{}", update.changed),
+///         Err(errors) => eprintln!("Keeping old configuration: {:?}", errors),
+///     }
+/// }
+/// ```
+pub struct ConfigWatcher {
+    shared: Arc<Shared>,
+    _watcher: Arc<DirWatcher>,
+}
+
+impl ConfigWatcher {
+    /// Load the configuration and begin watching its files, using the
+    /// default [WatchOptions].
+    ///
+    /// # Returns
+    ///
+    /// Returns the watcher, or a list of errors if the configuration could not
+    /// be loaded or the directories could not be watched.
+    pub fn new(loader: ConfigLoader) -> Result<Self, Vec<FileError>> {
+        Self::with_options(loader, &WatchOptions::default())
+    }
+
+    /// Load the configuration and begin watching its files, using the
+    /// specified [WatchOptions].
+    ///
+    /// See [ConfigWatcher::new] for more information.
+    pub fn with_options(loader: ConfigLoader, options: &WatchOptions)
+    -> Result<Self, Vec<FileError>> {
+        let (conf, _) = loader.load_blocking()?;
+        let files = watched_files(&loader, &conf);
+        let dirs = watched_dirs(&files);
+
+        let (tx, rx) = mpsc::channel();
+
+        let watcher = if options.poll {
+            start_polling(tx, options, &dirs)
+        } else {
+            notify::recommended_watcher(tx.clone())
+                .and_then(|w| start(w, &dirs))
+                .or_else(|_| start_polling(tx, options, &dirs))
+        }.map_err(|e| vec![notify_error(e, &dirs)])?;
+        let watcher = Arc::new(Mutex::new(watcher));
+
+        let shared = Arc::new(Shared {
+            config: RwLock::new(Arc::new(conf)),
+            subscribers: Mutex::new(vec![]),
+        });
+
+        // The thread must not keep the watcher alive, as dropping it is what
+        // ends the thread.
+        let thread_shared = shared.clone();
+        let thread_watcher = Arc::downgrade(&watcher);
+        let debounce = options.debounce;
+
+        std::thread::spawn(move || {
+            let watching = Watching { watcher: thread_watcher, files, dirs };
+            reload_on_change(loader, watching, rx, &thread_shared, debounce)
+        });
+
+        Ok(Self { shared, _watcher: watcher })
+    }
+
+    /// Retrieve the current configuration.
+    pub fn config(&self) -> Arc<Config> {
+        self.shared.config.read()
+            .expect("Config lock is poisoned")
+            .clone()
+    }
+
+    /// Receive the result of every subsequent reload.
+    ///
+    /// Reloads that do not change any values are not reported.
+    pub fn subscribe(&self) -> mpsc::Receiver<WatchResult> {
+        let (tx, rx) = mpsc::channel();
+
+        self.shared.subscribers.lock()
+            .expect("Subscriber lock is poisoned")
+            .push(tx);
+
+        rx
+    }
+}
+
+fn start<W>(mut watcher: W, dirs: &[PathBuf])
+-> notify::Result<Box<dyn Watcher + Send>>
+    where W: Watcher + Send + 'static,
+{
+    for dir in dirs {
+        watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(Box::new(watcher))
+}
+
+fn start_polling(
+    tx: mpsc::Sender<notify::Result<notify::Event>>,
+    options: &WatchOptions,
+    dirs: &[PathBuf]
+) -> notify::Result<Box<dyn Watcher + Send>> {
+    let config = notify::Config::default()
+        .with_poll_interval(options.poll_interval)
+        .with_compare_contents(true);
+
+    start(notify::PollWatcher::new(tx, config)?, dirs)
+}
+
+fn notify_error(err: notify::Error, dirs: &[PathBuf]) -> FileError {
+    let path = err.paths.first()
+        .or_else(|| dirs.first())
+        .cloned()
+        .unwrap_or_default();
+
+    let kind = match err.kind {
+        notify::ErrorKind::Io(ref e) => e.kind(),
+        notify::ErrorKind::PathNotFound => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+
+    FileError::IO((path, kind))
+}
+
+/// The absolute paths of the files that may affect the configuration.
+fn watched_files(loader: &ConfigLoader, conf: &Config) -> HashSet<PathBuf> {
+    let absolute = |p: &Path| std::env::current_dir()
+        .map(|dir| dir.join(p))
+        .unwrap_or_else(|_| p.into());
+
+    let included = conf.values.iter()
+        .filter_map(|(_, v)| file_path(&v.origin));
+
+    loader.paths().iter()
+        .map(PathBuf::as_path)
+        .chain(included)
+        .map(absolute)
+        .collect()
+}
+
+/// The existing directories containing `files`.
+fn watched_dirs(files: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let mut dirs: Vec<_> = files.iter()
+        .filter_map(|f| f.parent())
+        .filter(|d| d.is_dir())
+        .map(Path::to_path_buf)
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// The path of the file a value was read from, if any.
+fn file_path(origin: &Origin) -> Option<&Path> {
+    match *origin {
+        Origin::File { ref path, .. } => Some(path.as_path()),
+        Origin::Profile { ref origin, .. } => file_path(origin),
+        _ => None,
+    }
+}
+
+/// What the thread that reloads the configuration is watching.
+struct Watching {
+    watcher: Weak<DirWatcher>,
+    files: HashSet<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Watching {
+    /// Watch the files that may affect `conf`, adding and removing watched
+    /// directories as needed.
+    fn update(&mut self, loader: &ConfigLoader, conf: &Config) {
+        self.files = watched_files(loader, conf);
+
+        let watcher = match self.watcher.upgrade() {
+            Some(watcher) => watcher,
+            None => return,
+        };
+        let mut watcher = watcher.lock().expect("Watcher lock is poisoned");
+
+        let dirs = watched_dirs(&self.files);
+
+        for dir in self.dirs.iter().filter(|d| ! dirs.contains(d)) {
+            let _ = watcher.unwatch(dir);
+        }
+
+        // A directory that cannot be watched is tried again on the next
+        // reload.
+        self.dirs = dirs.into_iter()
+            .filter(|d| self.dirs.contains(d)
+                || watcher.watch(d, notify::RecursiveMode::NonRecursive)
+                    .is_ok())
+            .collect();
+    }
+}
+
+/// Reload the configuration whenever an event for one of the watched files
+/// is received, until the watcher is dropped.
+fn reload_on_change(
+    loader: ConfigLoader,
+    mut watching: Watching,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    shared: &Shared,
+    debounce: Duration,
+) {
+    let is_relevant = |event: &notify::Event, files: &HashSet<PathBuf>| {
+        ! matches!(event.kind, EventKind::Access(_))
+            && event.paths.iter().any(|p| files.contains(p))
+    };
+
+    while let Ok(event) = events.recv() {
+        match event {
+            Ok(ref e) if is_relevant(e, &watching.files) => {},
+            _ => continue,
+        }
+
+        while events.recv_timeout(debounce).is_ok() {}
+
+        let result = match loader.load_blocking() {
+            Ok((conf, _)) => {
+                watching.update(&loader, &conf);
+
+                let mut current = shared.config.write()
+                    .expect("Config lock is poisoned");
+
+                let changed = current.diff(&conf);
+                if changed.is_empty() { continue; }
+
+                let config = Arc::new(conf);
+                *current = config.clone();
+                Ok(ConfigUpdate { config, changed })
+            },
+            Err(errors) => Err(errors),
+        };
+
+        shared.subscribers.lock()
+            .expect("Subscriber lock is poisoned")
+            .retain(|s| s.send(result.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ParserOptions;
+
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("ub_watch_{}_{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn include_loader(path: &Path) -> ConfigLoader {
+        ConfigLoader::new("app")
+            .parser_options(ParserOptions::new().includes(true))
+            .file(path)
+    }
+
+    fn check_reload(name: &str, options: &WatchOptions) {
+        let dir = temp_dir(name);
+        let path = dir.join("app.ini");
+        std::fs::write(&path, "[Group]\nkept = 1\nchanged = 1\nremoved = 1\n")
+            .unwrap();
+
+        let loader = ConfigLoader::new("app").file(&path).required();
+        let watcher = ConfigWatcher::with_options(loader, options).unwrap();
+        let updates = watcher.subscribe();
+
+        std::fs::write(&path, "[Group]\nkept = 1\nchanged = 2\nadded = 2\n")
+            .unwrap();
+
+        let update = updates.recv_timeout(TIMEOUT).unwrap().unwrap();
+        assert_eq!(update.changed.keys(), vec![
+            ("Group", "changed"),
+            ("Group", "removed"),
+            ("Group", "added"),
+        ]);
+        assert_eq!(update.config[("Group", "changed")], "2");
+        assert_eq!(watcher.config()[("Group", "changed")], "2");
+
+        std::fs::write(&path, "[Group\nchanged = 3\n").unwrap();
+
+        let errs = updates.recv_timeout(TIMEOUT).unwrap().unwrap_err();
+        assert!(matches!(errs[0], FileError::Parse { .. }));
+        assert_eq!(watcher.config()[("Group", "changed")], "2");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_on_change() {
+        check_reload("native", &WatchOptions::default());
+    }
+
+    #[test]
+    fn reload_on_change_polling() {
+        let options = WatchOptions::new()
+            .poll(true)
+            .poll_interval(Duration::from_millis(50));
+
+        check_reload("poll", &options);
+    }
+
+    #[test]
+    fn watch_new_included_files() {
+        let dir = temp_dir("new_include");
+        let path = dir.join("app.ini");
+        let included = dir.join("new").join("included.ini");
+
+        std::fs::write(&path, "var = 1\n").unwrap();
+
+        let watcher = ConfigWatcher::new(include_loader(&path)).unwrap();
+        let updates = watcher.subscribe();
+
+        std::fs::create_dir(dir.join("new")).unwrap();
+        std::fs::write(&included, "var = 2\n").unwrap();
+        std::fs::write(&path, "[include]\nfile = new/included.ini\n").unwrap();
+
+        let update = updates.recv_timeout(TIMEOUT).unwrap().unwrap();
+        assert_eq!(watcher.config()["var"], "2");
+        assert_eq!(update.config["var"], "2");
+
+        std::fs::write(&included, "var = 3\n").unwrap();
+
+        let update = updates.recv_timeout(TIMEOUT).unwrap().unwrap();
+        assert_eq!(update.changed.keys(), vec![("DEFAULT", "var")]);
+        assert_eq!(watcher.config()["var"], "3");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watch_included_files() {
+        let dir = temp_dir("include");
+        let path = dir.join("app.ini");
+        let included = dir.join("sub").join("included.ini");
+
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(&path, "[include]\nfile = sub/included.ini\n").unwrap();
+        std::fs::write(&included, "var = 1\n").unwrap();
+
+        let watcher = ConfigWatcher::new(include_loader(&path)).unwrap();
+        let updates = watcher.subscribe();
+
+        std::fs::write(&included, "var = 2\n").unwrap();
+
+        let update = updates.recv_timeout(TIMEOUT).unwrap().unwrap();
+        assert_eq!(update.changed.keys(), vec![("DEFAULT", "var")]);
+        assert_eq!(watcher.config()["var"], "2");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
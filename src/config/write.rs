@@ -0,0 +1,226 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Atomic file writes
+//!
+//! A file is replaced by writing a temporary file in the same directory,
+//! syncing it to disk, and renaming it over the original, so that a crash
+//! leaves either the old or the new contents in place. The original file's
+//! permissions (and, on Unix, its owner and group where permitted) are kept.
+//! If the path is a symbolic link, the file it refers to is replaced.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::FileError;
+
+
+/// Distinguishes the temporary files of concurrent writes within a process.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Replace the contents of the file at `path` with `text`.
+///
+/// If `backup` is true and the file exists, its previous contents are kept
+/// in a file of the same name with `.bak` appended.
+pub(super) fn write_file(path: &Path, text: &str, backup: bool)
+-> Result<(), FileError> {
+    write_atomic(path, text.as_bytes(), backup)
+        .map_err(|e| FileError::IO((path.into(), e.kind())))
+}
+
+fn write_atomic(path: &Path, data: &[u8], backup: bool) -> io::Result<()> {
+    let path = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => path.to_owned(),
+    };
+    let original = fs::metadata(&path).ok();
+
+    let temp = temp_path(&path);
+    let result = write_temp(&temp, data, original.as_ref())
+        .and_then(|_| match original {
+            Some(_) if backup => backup_file(&path),
+            _ => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temp, &path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+
+    sync_dir(&path)
+}
+
+/// Write `data` to a new temporary file with the metadata of the original
+/// file, if any, and sync it to disk.
+fn write_temp(temp: &Path, data: &[u8], original: Option<&fs::Metadata>)
+-> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)?;
+
+    if let Some(meta) = original {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt as _;
+
+            // Only a privileged process can give a file away; keep the
+            // default owner otherwise.
+            let _ = std::os::unix::fs::fchown(
+                &file, Some(meta.uid()), Some(meta.gid())
+            );
+        }
+
+        file.set_permissions(meta.permissions())?;
+    }
+
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Keep the current contents of `path` as `path.bak`.
+fn backup_file(path: &Path) -> io::Result<()> {
+    let mut backup = OsString::from(path.as_os_str());
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+
+    match fs::remove_file(&backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {},
+    }
+
+    // The original file is about to be replaced, so a hard link preserves it
+    // without copying; not every file system supports them.
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+
+    Ok(())
+}
+
+/// A path for a temporary file in the same directory as `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    path.with_file_name(name)
+}
+
+/// Sync the directory containing `path` so that a rename is durable.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if ! dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("ub_write_{}_{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn replace_and_backup() {
+        let dir = temp_dir("backup");
+        let path = dir.join("app.ini");
+
+        write_file(&path, "a = 1\n", true).unwrap();
+        assert_eq!(entries(&dir), vec!["app.ini"]);
+
+        write_file(&path, "a = 2\n", false).unwrap();
+        assert_eq!(entries(&dir), vec!["app.ini"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 2\n");
+
+        write_file(&path, "a = 3\n", true).unwrap();
+        write_file(&path, "a = 4\n", true).unwrap();
+        assert_eq!(entries(&dir), vec!["app.ini", "app.ini.bak"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 4\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("app.ini.bak")).unwrap(),
+            "a = 3\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_keep_the_original() {
+        let dir = temp_dir("failed");
+        let path = dir.join("app.ini");
+        fs::write(&path, "a = 1\n").unwrap();
+        fs::create_dir(dir.join("app.ini.bak")).unwrap();
+
+        let err = write_file(&path, "a = 2\n", true).unwrap_err();
+        assert!(matches!(err, FileError::IO((ref p, _)) if p == &path));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 1\n");
+        assert_eq!(entries(&dir), vec!["app.ini", "app.ini.bak"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keep_permissions_and_links() {
+        use std::os::unix::fs::{symlink, PermissionsExt as _};
+
+        let dir = temp_dir("perms");
+        let path = dir.join("secret.ini");
+        let link = dir.join("link.ini");
+        fs::write(&path, "a = 1\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink("secret.ini", &link).unwrap();
+
+        write_file(&link, "a = 2\n", true).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 2\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(
+            fs::metadata(dir.join("secret.ini.bak")).unwrap()
+                .permissions().mode() & 0o777,
+            0o600
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}